@@ -0,0 +1,264 @@
+use cell::compute::{bake, format_reference, resolve_range, resolve_reference};
+use cell::csv::{self, Export};
+use cell::file::{self, FileError};
+use cell::state::{Address, State};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+const CONVERT_USAGE: &str = "Usage: cell --convert <from> <to> [--raw] [--delimiter <char>]";
+const EVAL_USAGE: &str =
+    "Usage: cell --eval [<sheet> | -] [--out <file>] [--cell <cell or range>] \
+[--delimiter <char>] [--iterations <n>] [--tolerance <x>]";
+
+// Convert a sheet between the native format and delimited text, picking each format from
+// its file extension (or --delimiter). Delimited output holds the computed results
+// unless --raw is given.
+pub fn convert(args: &[String]) -> Result<(), String> {
+    let mut paths = Vec::new();
+    let mut export = Export::Computed;
+    let mut delimiter = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--raw" => export = Export::Raw,
+            "--delimiter" => delimiter = Some(parse_delimiter(args.next())?),
+            _ => paths.push(Path::new(arg)),
+        }
+    }
+
+    let [from, to] = paths[..] else {
+        return Err(CONVERT_USAGE.to_string());
+    };
+
+    let mut state = load(Some(from), delimiter)?;
+    bake(&mut state);
+
+    match delimiter.or(file::delimiter(to)) {
+        Some(delimiter) => file::export(&state, to, delimiter, export),
+        None => file::save(&state, to),
+    }
+    .map_err(|err| format!("Could not write {}: {}", to.display(), err))
+}
+
+// Compute a sheet without the TUI, and write its results to a file or print them (or
+// just the given cell or range). The sheet is read from stdin if no file is given, as
+// the native format if it starts with its header and as CSV otherwise. --iterations and
+// --tolerance solve cycles by iteration, overriding the sheet's own settings. Returns
+// whether every cell computed without an error; the ones that didn't are listed on stderr.
+pub fn eval(args: &[String]) -> Result<bool, String> {
+    let mut sheet = None;
+    let mut out = None;
+    let mut area = None;
+    let mut delimiter = None;
+    let mut settings = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--iterations" | "--tolerance" => {
+                settings.push((&arg[2..], args.next().ok_or(EVAL_USAGE)?.as_str()))
+            }
+            "--out" => out = Some(Path::new(args.next().ok_or(EVAL_USAGE)?)),
+            "--cell" => area = Some(parse_area(args.next().ok_or(EVAL_USAGE)?)?),
+            "--delimiter" => delimiter = Some(parse_delimiter(args.next())?),
+            "-" if sheet.is_none() => sheet = Some(None),
+            _ if sheet.is_none() && !arg.starts_with("--") => sheet = Some(Some(Path::new(arg))),
+            _ => return Err(EVAL_USAGE.to_string()),
+        }
+    }
+
+    let mut state = load(sheet.flatten(), delimiter)?;
+    if !settings.is_empty() {
+        let mut iteration = state.iteration.unwrap_or_default();
+        for (key, value) in settings {
+            iteration.set(key, value)?;
+        }
+        state.set_iteration(Some(iteration));
+    }
+    bake(&mut state);
+
+    let area = area.unwrap_or(((0, 0), (u16::MAX, u16::MAX)));
+    match out {
+        Some(out) => {
+            let delimiter = delimiter.or(file::delimiter(out)).unwrap_or(',');
+            fs::write(
+                out,
+                csv::write_area(&state, area, delimiter, Export::Computed),
+            )
+            .map_err(|err| format!("Could not write {}: {}", out.display(), err))?;
+        }
+        None => print!("{}", write_area(&state, area, delimiter.unwrap_or(','))),
+    }
+
+    let mut ok = true;
+    for (addr, cell) in state.cells() {
+        if let Some(err) = &cell.computed.error {
+            eprintln!("{}: {}", format_reference(addr), err);
+            ok = false;
+        }
+    }
+
+    Ok(ok)
+}
+
+// The computed results of a cell on a line of its own, or of a range as delimited text
+fn write_area(state: &State, area: (Address, Address), delimiter: char) -> String {
+    if area.0 == area.1 {
+        return format!("{}\n", state.get_at(area.0).computed.display);
+    }
+
+    csv::write_area(state, area, delimiter, Export::Computed)
+}
+
+// Read a sheet from a file (or stdin), as delimited text if the delimiter is given or the
+// file name asks for it
+fn load(path: Option<&Path>, delimiter: Option<char>) -> Result<State, String> {
+    let text = match path {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?,
+        None => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|err| format!("Could not read stdin: {}", err))?;
+            text
+        }
+    };
+
+    read(&text, path, delimiter).map_err(|err| err.to_string())
+}
+
+// Read a sheet from the text of `path`, or of stdin if there's no path, in which case
+// the native format is told apart from CSV by its header
+fn read(text: &str, path: Option<&Path>, delimiter: Option<char>) -> Result<State, FileError> {
+    let delimiter = delimiter.or(path.and_then(file::delimiter));
+    match delimiter {
+        Some(delimiter) => csv::read(text, delimiter),
+        None if path.is_some() || text.starts_with("cell ") => file::read_sheet(text),
+        None => csv::read(text, ','),
+    }
+}
+
+// A single cell (B2) or a range (A1:C3, B:B)
+fn parse_area(arg: &str) -> Result<(Address, Address), String> {
+    if let Ok(addr) = resolve_reference(arg) {
+        return Ok((addr, addr));
+    }
+
+    let range = resolve_range(arg).map_err(|err| err.message)?;
+    Ok((range.start, range.end))
+}
+
+fn parse_delimiter(arg: Option<&String>) -> Result<char, String> {
+    let Some(arg) = arg else {
+        return Err("Missing delimiter".to_string());
+    };
+
+    let mut chars = arg.chars();
+    match (arg.as_str(), chars.next(), chars.next()) {
+        ("tab" | "\\t", ..) => Ok('\t'),
+        (_, Some(char), None) => Ok(char),
+        _ => Err(format!(
+            "Delimiter should be a single character, got {}",
+            arg
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cell::state::DisplayCell;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_read() {
+        // stdin is the native format if it starts with its header, and CSV otherwise
+        let state = read("cell 1\nB1\t=1 + 1\n", None, None).unwrap();
+        assert_eq!(state.get_at((0, 1)).value, "=1 + 1");
+        let state = read("cell,1\n", None, None).unwrap();
+        assert_eq!(state.get_at((0, 1)).value, "1");
+
+        // files go by their name, unless the delimiter is given
+        let tsv = Path::new("data.tsv");
+        let state = read("a\tb,c\n", Some(tsv), None).unwrap();
+        assert_eq!(state.get_at((0, 1)).value, "b,c");
+        let state = read("a;b\n", Some(tsv), Some(';')).unwrap();
+        assert_eq!(state.get_at((0, 1)).value, "b");
+        assert_eq!(
+            read("a,b\n", Some(Path::new("sheet.cell")), None)
+                .err()
+                .map(|err| err.to_string()),
+            Some("Not a cell file".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_area() {
+        assert_eq!(parse_area("B2"), Ok(((1, 1), (1, 1))));
+        assert_eq!(parse_area("A1:C3"), Ok(((0, 0), (2, 2))));
+        assert_eq!(parse_area("B:B"), Ok(((0, 1), (u16::MAX, 1))));
+        assert!(parse_area("B").is_err());
+    }
+
+    #[test]
+    fn test_write_area() {
+        let mut state = State::blank();
+        state.set_at((0, 0), DisplayCell::new("1".to_string()));
+        state.set_at((0, 1), DisplayCell::new("=A1 * 2".to_string()));
+        state.set_at((1, 1), DisplayCell::new("=A1 / 4".to_string()));
+        bake(&mut state);
+
+        // a single cell is its result alone, and a range is delimited text
+        assert_eq!(write_area(&state, ((0, 1), (0, 1)), ','), "2\n");
+        assert_eq!(write_area(&state, ((5, 5), (5, 5)), ','), "\n");
+        assert_eq!(
+            write_area(&state, ((0, 0), (1, 1)), '\t'),
+            "1\t2\r\n\t0.25\r\n"
+        );
+        assert_eq!(
+            write_area(&state, ((0, 1), (u16::MAX, 1)), ','),
+            "2\r\n0.25\r\n"
+        );
+    }
+
+    #[test]
+    fn test_eval() {
+        let dir = std::env::temp_dir().join(format!("cell-test-eval-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sheet = dir.join("sheet.cell");
+        let out = dir.join("out.tsv");
+        let path = |path: &Path| path.to_str().unwrap().to_string();
+
+        fs::write(&sheet, "cell 1\nA1\t2\nB1\t=A1 * 3\n").unwrap();
+        assert_eq!(
+            eval(&[path(&sheet), "--out".to_string(), path(&out)]),
+            Ok(true)
+        );
+        assert_eq!(fs::read_to_string(&out).unwrap(), "2\t6\r\n");
+
+        // a sheet with an error still writes its results, but isn't ok
+        fs::write(&sheet, "cell 1\nA1\t=1 / 0\nB1\t=A1 * 3\n").unwrap();
+        assert_eq!(
+            eval(&[path(&sheet), "--out".to_string(), path(&out)]),
+            Ok(false)
+        );
+        assert_eq!(fs::read_to_string(&out).unwrap(), "#DIV/0!\t#DIV/0!\r\n");
+
+        assert_eq!(eval(&args(&["--out"])), Err(EVAL_USAGE.to_string()));
+        assert_eq!(
+            eval(&args(&["a.cell", "b.cell"])),
+            Err(EVAL_USAGE.to_string())
+        );
+        assert!(eval(&[path(&dir.join("missing.cell"))])
+            .unwrap_err()
+            .starts_with("Could not read"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
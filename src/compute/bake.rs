@@ -1,168 +1,859 @@
-use crate::compute::node::resolve_reference;
-use crate::compute::parse::parse;
-use crate::state::State;
+use crate::compute::cycles::find_cycles;
+use crate::compute::error::{CellError, ComputeError};
+use crate::compute::node::format_reference;
+use crate::compute::parse;
+use crate::compute::value::Value;
+use crate::state::{Address, CellComputation, State};
+use std::collections::{HashMap, HashSet, VecDeque};
 
-// Populate the COMPUTED value for a State
+/// Settings for solving cycles by fixed-point iteration instead of reporting them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Iteration {
+    /// How many passes a cycle gets to settle before it's reported as #CONV!
+    pub max_iterations: usize,
+    /// The largest change between two passes for a cycle to count as settled
+    pub tolerance: f64,
+}
+
+impl Iteration {
+    /// Change one setting from its `key=value` form, as given to `:set` or in a file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let bad = || format!("Bad value for {}: {}", key, value);
+        match key {
+            "iterations" => self.max_iterations = value.parse().map_err(|_| bad())?,
+            "tolerance" => {
+                self.tolerance = match value.parse::<f64>() {
+                    Ok(tolerance) if tolerance.is_finite() && tolerance >= 0.0 => tolerance,
+                    _ => return Err(bad()),
+                }
+            }
+            _ => return Err(format!("Unknown setting {}", key)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Iteration {
+    fn default() -> Self {
+        Iteration {
+            max_iterations: 100,
+            tolerance: 0.001,
+        }
+    }
+}
+
+/// Populate the COMPUTED value for a State. Only cells edited since the last bake and the
+/// cells that depend on them are recomputed; the first bake computes everything.
 pub fn bake(state: &mut State) {
-    // Clear all cells
-    for row in state.content.iter_mut() {
-        for cell in row.iter_mut() {
-            cell.computed.clear();
+    let changed: HashSet<Address> = match state.graph.take_dirty() {
+        Some(dirty) => dirty,
+        None => all_cells(state),
+    };
+
+    // Re-parse changed cells
+    for &addr in changed.iter() {
+        let value = &state.get_at(addr).value;
+        if value.is_empty() {
+            state.graph.remove(addr);
+        } else {
+            let formula = parse::parse_cell(value);
+            state.graph.set_formula(addr, formula);
         }
     }
 
-    fn parse_cell(state: &mut State, addr: (u16, u16), loop_stack: &Vec<(u16, u16)>) {
-        if state.get_at(addr).computed.is_computed {
-            return;
+    // Collect everything downstream of a change
+    let mut dependents: HashMap<Address, Vec<Address>> = HashMap::new();
+    let mut stack: Vec<Address> = changed.into_iter().collect();
+    while let Some(addr) = stack.pop() {
+        if dependents.contains_key(&addr) {
+            continue;
         }
 
-        let mut cell = state.get_at(addr).clone();
+        let next = state.graph.dependents(addr);
+        stack.extend(next.iter().copied());
+        dependents.insert(addr, next);
+    }
 
-        if cell.value.is_empty() {
-            return;
+    for &addr in dependents.keys() {
+        if let Some(computed) = state.computation_mut(addr) {
+            computed.clear();
         }
+    }
 
-        let parse_result = parse(&cell.value.as_str());
+    // Cells in a cycle can't be ordered, so only the edges into their cycle count towards
+    // ordering and the cycle is either iterated or reported once it's reached
+    let cycles = find_cycles(&dependents);
+    let is_internal =
+        |from: &Address, to: &Address| cycles.get(from).is_some_and(|members| members.contains(to));
 
-        let Ok(node) = parse_result else {
-            cell.computed.set_error(parse_result.unwrap_err());
-            state.set_at(addr, cell);
-            return;
+    // Order the affected cells so that each comes after every cell it reads from
+    let mut in_degree: HashMap<Address, usize> = dependents.keys().map(|&a| (a, 0)).collect();
+    for (from, next) in dependents.iter() {
+        for addr in next {
+            if !is_internal(from, addr) {
+                *in_degree.get_mut(addr).unwrap() += 1;
+            }
+        }
+    }
+
+    let mut queue: VecDeque<Address> = in_degree
+        .iter()
+        .filter(|(_, &degree)| degree == 0)
+        .map(|(&addr, _)| addr)
+        .collect();
+
+    // A cycle is solved as a whole once every one of its members is ready
+    let mut ready: HashMap<Address, usize> = HashMap::new();
+
+    while let Some(addr) = queue.pop_front() {
+        let solved = match cycles.get(&addr) {
+            Some(members) => {
+                let count = ready.entry(members[0]).or_default();
+                *count += 1;
+                if *count < members.len() {
+                    continue;
+                }
+
+                solve_cycle(state, members);
+                members.clone()
+            }
+            None => {
+                compute_cell(state, addr);
+                vec![addr]
+            }
         };
 
-        // compute all references
-        let mut new_loop_stack = loop_stack.clone();
-        new_loop_stack.push(addr);
+        for from in solved {
+            for next in dependents[&from].iter() {
+                if is_internal(&from, next) {
+                    continue;
+                }
+
+                let degree = in_degree.get_mut(next).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push_back(*next);
+                }
+            }
+        }
+    }
+}
+
+// Every cell that exists in the sheet
+fn all_cells(state: &State) -> HashSet<Address> {
+    state.cells().map(|(addr, _)| addr).collect()
+}
 
-        for reference in &node.get_references() {
-            let addr_result = resolve_reference(reference);
+fn solve_cycle(state: &mut State, members: &[Address]) {
+    let Some(iteration) = state.iteration else {
+        let names: Vec<String> = members.iter().map(|&m| format_reference(m)).collect();
+        let message = format!("Cycle between {}", names.join(", "));
+        for &addr in members {
+            set_error(
+                state,
+                addr,
+                ComputeError::new(CellError::Cycle, message.clone()),
+            );
+        }
+        return;
+    };
 
-            let Ok(ref_addr) = addr_result else {
-                cell.computed.set_error(addr_result.unwrap_err());
-                state.set_at(addr, cell);
-                return;
+    // Start from the values of the last bake, so an edit nearby converges quickly
+    for &addr in members {
+        if let Some(computed) = state.computation_mut(addr) {
+            let seed = match computed.value {
+                Some(Value::Number(num)) if computed.error.is_none() => num,
+                _ => 0.0,
             };
+            computed.set_computed(Value::Number(seed));
+        }
+    }
+
+    // A member that errors keeps its last value for the others to read, since an error
+    // part way through (from the seed, say) may go away as the values settle. Errors are
+    // only final once the cycle settles or runs out of iterations.
+    let mut errors: HashMap<Address, ComputeError> = HashMap::new();
+
+    for _ in 0..iteration.max_iterations {
+        let mut converged = true;
+        errors.clear();
 
-            if loop_stack.contains(&ref_addr) {
-                cell.computed
-                    .set_error(format!("Data contains a cycle! {:?}", loop_stack));
-                state.set_at(addr, cell);
-                return;
+        for &addr in members {
+            let before = state.get_at(addr).computed.value.clone();
+            compute_cell(state, addr);
+
+            let Some(after) = state.computation_mut(addr) else {
+                continue;
+            };
+            if let Some(err) = after.error.take() {
+                errors.insert(addr, err);
+                if let Some(before) = before.clone() {
+                    after.set_computed(before);
+                }
             }
 
-            parse_cell(state, ref_addr, &new_loop_stack);
+            converged &= match (before, after.value.as_ref()) {
+                (Some(Value::Number(a)), Some(Value::Number(b))) => {
+                    (a - b).abs() <= iteration.tolerance
+                }
+                (before, after) => before.as_ref() == after,
+            };
         }
 
-        match node.compute(state) {
-            Ok(val) => cell.computed.set_computed(val),
-            Err(err) => cell.computed.set_error(err),
+        if converged {
+            // the errors left are final, and so is anything in the cycle that reads them
+            for (addr, err) in errors.iter() {
+                if let Some(computed) = state.computation_mut(*addr) {
+                    computed.set_error(err.clone());
+                }
+            }
+
+            let mut spreading = !errors.is_empty();
+            while spreading {
+                spreading = false;
+                for &addr in members {
+                    if errors.contains_key(&addr) {
+                        continue;
+                    }
+
+                    compute_cell(state, addr);
+                    if let Some(err) = &state.get_at(addr).computed.error {
+                        errors.insert(addr, err.clone());
+                        spreading = true;
+                    }
+                }
+            }
+            return;
         }
+    }
+
+    let message = format!(
+        "Did not converge after {} iterations",
+        iteration.max_iterations
+    );
+    for &addr in members {
+        let err = errors
+            .remove(&addr)
+            .unwrap_or_else(|| ComputeError::new(CellError::Converge, message.clone()));
+        set_error(state, addr, err);
+    }
+}
 
-        state.set_at(addr, cell);
+fn set_error(state: &mut State, addr: Address, err: ComputeError) {
+    if let Some(computed) = state.computation_mut(addr) {
+        computed.set_error(err.at(addr));
     }
+}
+
+fn compute_cell(state: &mut State, addr: Address) {
+    let result = match state.graph.formula(addr) {
+        Some(Ok(node)) => Some(node.compute(state)),
+        Some(Err(err)) => Some(Err(err.clone())),
+        None => None,
+    };
 
-    let num_rows = state.content.len();
-    for r in 0..num_rows {
-        let num_columns = state.content[r].len();
-        for c in 0..num_columns {
-            parse_cell(state, (r as u16, c as u16), &vec![]);
+    let Some(computed) = state.computation_mut(addr) else {
+        return;
+    };
+
+    match result {
+        Some(Ok(val)) => computed.set_computed(val),
+        // Cells outside a cycle that read from one say which cell they got it through
+        Some(Err(err))
+            if matches!(err.kind, CellError::Cycle | CellError::DependsOnCycle)
+                && err.origin.is_some() =>
+        {
+            let message = format!(
+                "Depends on a cycle through {}",
+                format_reference(err.origin.unwrap())
+            );
+            computed.set_error(ComputeError::new(CellError::DependsOnCycle, message).at(addr))
         }
+        Some(Err(err)) => computed.set_error(err.at(addr)),
+        None => *computed = CellComputation::new(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compute::node::resolve_reference;
+    use crate::compute::Value;
     use crate::state::DisplayCell;
 
-    const REF: fn(&str) -> (u16, u16) = |s: &str| resolve_reference(&s.to_string()).unwrap();
+    const REF: fn(&str) -> (u16, u16) = |s: &str| resolve_reference(s).unwrap();
 
     #[test]
     fn test_bake() {
         let mut state = State::blank();
 
-        state.set_at(REF("A1"), DisplayCell::new("4 * ( 2 + 3 )".to_string()));
-        state.set_at(REF("B1"), DisplayCell::new("A1 * 2".to_string()));
-        state.set_at(REF("C1"), DisplayCell::new("B1 * 2".to_string()));
+        state.set_at(REF("A1"), DisplayCell::new("=4 * ( 2 + 3 )".to_string()));
+        state.set_at(REF("B1"), DisplayCell::new("=A1 * 2".to_string()));
+        state.set_at(REF("C1"), DisplayCell::new("=B1 * 2".to_string()));
         bake(&mut state);
 
         let a1 = state.get_at(REF("A1"));
-        assert_eq!(a1.computed.is_computed, true);
-        assert_eq!(a1.computed.error, false);
+        assert!(a1.computed.is_computed);
+        assert!(a1.computed.error.is_none());
         assert_eq!(a1.computed.display, "20");
-        assert_eq!(a1.computed.value, Some(20.0));
+        assert_eq!(a1.computed.value, Some(Value::Number(20.0)));
 
         let b1 = state.get_at(REF("B1"));
-        assert_eq!(b1.computed.is_computed, true);
-        assert_eq!(b1.computed.error, false);
+        assert!(b1.computed.is_computed);
+        assert!(b1.computed.error.is_none());
         assert_eq!(b1.computed.display, "40");
-        assert_eq!(b1.computed.value, Some(40.0));
+        assert_eq!(b1.computed.value, Some(Value::Number(40.0)));
 
         let c1 = state.get_at(REF("C1"));
-        assert_eq!(c1.computed.is_computed, true);
-        assert_eq!(c1.computed.error, false);
+        assert!(c1.computed.is_computed);
+        assert!(c1.computed.error.is_none());
         assert_eq!(c1.computed.display, "80");
-        assert_eq!(c1.computed.value, Some(80.0));
+        assert_eq!(c1.computed.value, Some(Value::Number(80.0)));
     }
 
     #[test]
     fn test_bake_2() {
         let mut state = State::blank();
         state.set_at(REF("C3"), DisplayCell::new("100".to_string()));
-        state.set_at(REF("C4"), DisplayCell::new("C3 + 1".to_string()));
-        state.set_at(REF("C5"), DisplayCell::new("C4 + 1".to_string()));
+        state.set_at(REF("C4"), DisplayCell::new("=C3 + 1".to_string()));
+        state.set_at(REF("C5"), DisplayCell::new("=C4 + 1".to_string()));
         bake(&mut state);
 
         let a1 = state.get_at(REF("C3"));
-        assert_eq!(a1.computed.is_computed, true);
-        assert_eq!(a1.computed.error, false);
+        assert!(a1.computed.is_computed);
+        assert!(a1.computed.error.is_none());
         assert_eq!(a1.computed.display, "100");
-        assert_eq!(a1.computed.value, Some(100.0));
+        assert_eq!(a1.computed.value, Some(Value::Number(100.0)));
 
         let b1 = state.get_at(REF("C4"));
-        assert_eq!(b1.computed.is_computed, true);
-        assert_eq!(b1.computed.error, false);
+        assert!(b1.computed.is_computed);
+        assert!(b1.computed.error.is_none());
         assert_eq!(b1.computed.display, "101");
-        assert_eq!(b1.computed.value, Some(101.0));
+        assert_eq!(b1.computed.value, Some(Value::Number(101.0)));
 
         let c1 = state.get_at(REF("C5"));
-        assert_eq!(c1.computed.is_computed, true);
-        assert_eq!(c1.computed.error, false);
+        assert!(c1.computed.is_computed);
+        assert!(c1.computed.error.is_none());
         assert_eq!(c1.computed.display, "102");
-        assert_eq!(c1.computed.value, Some(102.0));
+        assert_eq!(c1.computed.value, Some(Value::Number(102.0)));
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_bake_cycle() {
         let mut state = State::blank();
-        state.set_at(REF("A1"), DisplayCell::new("5 + C3".to_string()));
-        state.set_at(REF("B2"), DisplayCell::new("A1 - 4".to_string()));
-        state.set_at(REF("C3"), DisplayCell::new("B2 * 10".to_string()));
+        state.set_at(REF("A1"), DisplayCell::new("=5 + C3".to_string()));
+        state.set_at(REF("B2"), DisplayCell::new("=A1 - 4".to_string()));
+        state.set_at(REF("C3"), DisplayCell::new("=B2 * 10".to_string()));
         state.set_at(REF("D4"), DisplayCell::new("3.14".to_string()));
+        state.set_at(REF("E5"), DisplayCell::new("=A1 + D4".to_string()));
+        state.set_at(REF("E6"), DisplayCell::new("=E5".to_string()));
         bake(&mut state);
 
         let a1 = state.get_at(REF("A1"));
-        assert_eq!(a1.computed.is_computed, true);
-        assert_eq!(a1.computed.error, true);
+        assert!(a1.computed.is_computed);
+        assert!(a1.computed.error.is_some());
         // assert_eq!(a1.computed.display, "100");
         assert_eq!(a1.computed.value, None);
 
         let b1 = state.get_at(REF("B2"));
-        assert_eq!(b1.computed.is_computed, true);
-        assert_eq!(b1.computed.error, true);
+        assert!(b1.computed.is_computed);
+        assert!(b1.computed.error.is_some());
         // assert_eq!(b1.computed.display, "101");
         assert_eq!(b1.computed.value, None);
 
         let c1 = state.get_at(REF("C3"));
-        assert_eq!(c1.computed.is_computed, true);
-        assert_eq!(c1.computed.error, true);
+        assert!(c1.computed.is_computed);
+        assert!(c1.computed.error.is_some());
         // assert_eq!(c1.computed.display, "102");
         assert_eq!(c1.computed.value, None);
 
         let c1 = state.get_at(REF("D4"));
-        assert_eq!(c1.computed.is_computed, true);
-        assert_eq!(c1.computed.error, false);
+        assert!(c1.computed.is_computed);
+        assert!(c1.computed.error.is_none());
         assert_eq!(c1.computed.display, "3.14");
-        assert_eq!(c1.computed.value, Some(3.14));
+        assert_eq!(c1.computed.value, Some(Value::Number(3.14)));
+
+        // cycle members list the whole cycle
+        let error = |s: &str| state.get_at(REF(s)).computed.error.clone().unwrap();
+        assert_eq!(error("A1").kind, CellError::Cycle);
+        assert_eq!(error("A1").message, "Cycle between A1, B2, C3");
+        assert_eq!(error("C3").kind, CellError::Cycle);
+        assert_eq!(error("C3").message, "Cycle between A1, B2, C3");
+
+        // cells downstream of the cycle are not part of it
+        assert_eq!(error("E5").kind, CellError::DependsOnCycle);
+        assert_eq!(error("E5").message, "Depends on a cycle through A1");
+        assert_eq!(error("E6").kind, CellError::DependsOnCycle);
+        assert_eq!(error("E6").message, "Depends on a cycle through E5");
+        assert_eq!(state.get_at(REF("E6")).computed.display, "#DEPCY!");
+
+        // breaking the cycle clears it
+        state.set_at(REF("C3"), DisplayCell::new("10".to_string()));
+        bake(&mut state);
+        assert_eq!(
+            state.get_at(REF("E6")).computed.value,
+            Some(Value::Number(18.14))
+        );
+    }
+
+    #[test]
+    fn test_bake_function() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("1".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("2".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("=SUM(A1, A2, 3)".to_string()));
+        state.set_at(REF("A4"), DisplayCell::new("=AVG(A1, A3) * 2".to_string()));
+        bake(&mut state);
+
+        assert_eq!(
+            state.get_at(REF("A3")).computed.value,
+            Some(Value::Number(6.0))
+        );
+        assert_eq!(
+            state.get_at(REF("A4")).computed.value,
+            Some(Value::Number(7.0))
+        );
+    }
+
+    #[test]
+    fn test_bake_range() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("1".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("2".to_string()));
+        state.set_at(REF("B1"), DisplayCell::new("=A2 + 1".to_string()));
+        state.set_at(REF("B2"), DisplayCell::new("4".to_string()));
+        state.set_at(REF("C1"), DisplayCell::new("=SUM(A1:B2)".to_string()));
+        state.set_at(
+            REF("C2"),
+            DisplayCell::new("=AVG(A:A) + COUNT(1:1)".to_string()),
+        );
+        state.set_at(REF("C3"), DisplayCell::new("=MAX(B2:A1, 10)".to_string()));
+        state.set_at(REF("C4"), DisplayCell::new("=A1:A2".to_string()));
+        bake(&mut state);
+
+        assert_eq!(
+            state.get_at(REF("C1")).computed.value,
+            Some(Value::Number(10.0))
+        );
+        assert_eq!(
+            state.get_at(REF("C2")).computed.value,
+            Some(Value::Number(4.5))
+        );
+        assert_eq!(
+            state.get_at(REF("C3")).computed.value,
+            Some(Value::Number(10.0))
+        );
+        assert!(state.get_at(REF("C4")).computed.error.is_some());
+    }
+
+    #[test]
+    fn test_bake_range_cycle() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("1".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=SUM(A:A)".to_string()));
+        bake(&mut state);
+
+        assert!(state.get_at(REF("A1")).computed.error.is_none());
+        assert!(state.get_at(REF("A2")).computed.error.is_some());
+    }
+
+    #[test]
+    fn test_bake_unary() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("-5".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=3 * -A1".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("=-(A1 + 2)".to_string()));
+        bake(&mut state);
+
+        assert_eq!(
+            state.get_at(REF("A1")).computed.value,
+            Some(Value::Number(-5.0))
+        );
+        assert_eq!(
+            state.get_at(REF("A2")).computed.value,
+            Some(Value::Number(15.0))
+        );
+        assert_eq!(
+            state.get_at(REF("A3")).computed.value,
+            Some(Value::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn test_bake_power_modulo() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("=2 ^ 3 ^ 2".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=-2 ^ 2".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("=7 % 3".to_string()));
+        state.set_at(REF("A4"), DisplayCell::new("=-7 % 3".to_string()));
+        state.set_at(REF("A5"), DisplayCell::new("=7 % 0".to_string()));
+        state.set_at(REF("A6"), DisplayCell::new("=-8 ^ 0.5".to_string()));
+        state.set_at(REF("A7"), DisplayCell::new("=(-8) ^ 0.5".to_string()));
+        bake(&mut state);
+
+        assert_eq!(
+            state.get_at(REF("A1")).computed.value,
+            Some(Value::Number(512.0))
+        );
+        assert_eq!(
+            state.get_at(REF("A2")).computed.value,
+            Some(Value::Number(-4.0))
+        );
+        assert_eq!(
+            state.get_at(REF("A3")).computed.value,
+            Some(Value::Number(1.0))
+        );
+        assert_eq!(
+            state.get_at(REF("A4")).computed.value,
+            Some(Value::Number(2.0))
+        );
+        assert!(state.get_at(REF("A5")).computed.error.is_some());
+        assert!(state.get_at(REF("A6")).computed.error.is_none());
+        assert!(state.get_at(REF("A7")).computed.error.is_some());
+    }
+
+    #[test]
+    fn test_bake_comparison() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("150".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=A1 > 100".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("=A1 <= 100".to_string()));
+        state.set_at(REF("A4"), DisplayCell::new("=A2 + A2 + A3".to_string()));
+        state.set_at(REF("A5"), DisplayCell::new("=A2 = TRUE".to_string()));
+        state.set_at(REF("A6"), DisplayCell::new("=1 <> 1".to_string()));
+        bake(&mut state);
+
+        let a2 = state.get_at(REF("A2"));
+        assert_eq!(a2.computed.value, Some(Value::Bool(true)));
+        assert_eq!(a2.computed.display, "TRUE");
+
+        let a3 = state.get_at(REF("A3"));
+        assert_eq!(a3.computed.value, Some(Value::Bool(false)));
+        assert_eq!(a3.computed.display, "FALSE");
+
+        assert_eq!(
+            state.get_at(REF("A4")).computed.value,
+            Some(Value::Number(2.0))
+        );
+        assert_eq!(
+            state.get_at(REF("A5")).computed.value,
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            state.get_at(REF("A6")).computed.value,
+            Some(Value::Bool(false))
+        );
+    }
+
+    #[test]
+    fn test_bake_text() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("Revenue".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("12".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("'30".to_string()));
+        state.set_at(REF("B1"), DisplayCell::new("=A1 & \": \" & A2".to_string()));
+        state.set_at(REF("B2"), DisplayCell::new("=SUM(A1:A3)".to_string()));
+        state.set_at(REF("B3"), DisplayCell::new("=A1 + 1".to_string()));
+        state.set_at(REF("B4"), DisplayCell::new("=A3 * 2".to_string()));
+        state.set_at(REF("B5"), DisplayCell::new("=A1 = \"REVENUE\"".to_string()));
+        bake(&mut state);
+
+        let a1 = state.get_at(REF("A1"));
+        assert!(a1.computed.error.is_none());
+        assert_eq!(a1.computed.display, "Revenue");
+
+        let b1 = state.get_at(REF("B1"));
+        assert_eq!(
+            b1.computed.value,
+            Some(Value::Text("Revenue: 12".to_string()))
+        );
+
+        assert_eq!(
+            state.get_at(REF("B2")).computed.value,
+            Some(Value::Number(12.0))
+        );
+        assert!(state.get_at(REF("B3")).computed.error.is_some());
+        assert_eq!(
+            state.get_at(REF("B4")).computed.value,
+            Some(Value::Number(60.0))
+        );
+        assert_eq!(
+            state.get_at(REF("B5")).computed.value,
+            Some(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn test_bake_logic() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("0".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("5".to_string()));
+        state.set_at(
+            REF("B1"),
+            DisplayCell::new("=IF(A1 = 0, 0, A2 / A1)".to_string()),
+        );
+        state.set_at(
+            REF("B2"),
+            DisplayCell::new("=IF(A1 > 0, \"pos\")".to_string()),
+        );
+        state.set_at(
+            REF("B3"),
+            DisplayCell::new("=IFERROR(A2 / A1, -1)".to_string()),
+        );
+        state.set_at(
+            REF("B4"),
+            DisplayCell::new("=AND(A2, A1 <> 0, A2 / A1)".to_string()),
+        );
+        state.set_at(REF("B5"), DisplayCell::new("=OR(A1:A2)".to_string()));
+        state.set_at(REF("B6"), DisplayCell::new("=NOT(A1)".to_string()));
+        state.set_at(
+            REF("B7"),
+            DisplayCell::new("=IF(A2 > 1, A2 / A1, 0)".to_string()),
+        );
+        bake(&mut state);
+
+        let value = |s: &str| state.get_at(REF(s)).computed.value.clone();
+
+        assert_eq!(value("B1"), Some(Value::Number(0.0)));
+        assert_eq!(value("B2"), Some(Value::Bool(false)));
+        assert_eq!(value("B3"), Some(Value::Number(-1.0)));
+        assert_eq!(value("B4"), Some(Value::Bool(false)));
+        assert_eq!(value("B5"), Some(Value::Bool(true)));
+        assert_eq!(value("B6"), Some(Value::Bool(true)));
+        assert!(state.get_at(REF("B7")).computed.error.is_some());
+    }
+
+    #[test]
+    fn test_bake_logic_cycle() {
+        // references in an untaken branch still count towards cycles
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("=IF(TRUE, 1, A2)".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=A1".to_string()));
+        bake(&mut state);
+
+        assert!(state.get_at(REF("A1")).computed.error.is_some());
+        assert!(state.get_at(REF("A2")).computed.error.is_some());
+    }
+
+    #[test]
+    fn test_bake_iterative() {
+        // balance depends on interest, which depends on balance
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("1000".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=A1 + A3".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("=A2 * 0.1".to_string()));
+        state.set_at(REF("A4"), DisplayCell::new("=A2 * 2".to_string()));
+        bake(&mut state);
+
+        let kind =
+            |state: &State, s: &str| state.get_at(REF(s)).computed.error.as_ref().map(|e| e.kind);
+        assert_eq!(kind(&state, "A2"), Some(CellError::Cycle));
+
+        state.set_iteration(Some(Iteration {
+            max_iterations: 100,
+            tolerance: 1e-9,
+        }));
+        bake(&mut state);
+
+        let number = |state: &State, s: &str| match state.get_at(REF(s)).computed.value {
+            Some(Value::Number(num)) => num,
+            _ => panic!("{} is not a number", s),
+        };
+        assert!((number(&state, "A2") - 1111.111111).abs() < 1e-6);
+        assert!((number(&state, "A3") - 111.111111).abs() < 1e-6);
+        assert!((number(&state, "A4") - 2222.222222).abs() < 1e-6);
+
+        // edits inside the cycle are iterated again
+        state.set_at(REF("A1"), DisplayCell::new("2000".to_string()));
+        bake(&mut state);
+        assert!((number(&state, "A2") - 2222.222222).abs() < 1e-6);
+
+        // a cycle that keeps growing never settles
+        state.set_at(REF("B1"), DisplayCell::new("=B2 + 1".to_string()));
+        state.set_at(REF("B2"), DisplayCell::new("=B1 * 2".to_string()));
+        state.set_at(REF("B3"), DisplayCell::new("=B1".to_string()));
+        bake(&mut state);
+        assert_eq!(kind(&state, "B1"), Some(CellError::Converge));
+        assert_eq!(kind(&state, "B2"), Some(CellError::Converge));
+        assert_eq!(kind(&state, "B3"), Some(CellError::Converge));
+        assert_eq!(
+            state
+                .get_at(REF("B1"))
+                .computed
+                .error
+                .as_ref()
+                .unwrap()
+                .message,
+            "Did not converge after 100 iterations"
+        );
+
+        state.set_iteration(None);
+        bake(&mut state);
+        assert_eq!(kind(&state, "A2"), Some(CellError::Cycle));
+        assert_eq!(kind(&state, "B1"), Some(CellError::Cycle));
+    }
+
+    #[test]
+    fn test_bake_iterative_errors() {
+        let mut state = State::blank();
+        state.set_iteration(Some(Iteration::default()));
+
+        // A1 divides by zero on the first pass, from the seed, but not once A2 settles
+        state.set_at(REF("A1"), DisplayCell::new("=100 / A2".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=A1 / 10 + 5".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("=A1".to_string()));
+
+        // an error that doesn't go away is final, and spreads through the cycle
+        state.set_at(REF("B1"), DisplayCell::new("=1 / 0 + B2".to_string()));
+        state.set_at(REF("B2"), DisplayCell::new("=B1".to_string()));
+        bake(&mut state);
+
+        let number = |s: &str| match state.get_at(REF(s)).computed.value {
+            Some(Value::Number(num)) => num,
+            _ => panic!("{} is not a number", s),
+        };
+        assert!((number("A2") - 6.531).abs() < 0.01);
+        assert!((number("A1") - 15.31).abs() < 0.01);
+        assert_eq!(number("A3"), number("A1"));
+
+        let kind = |s: &str| state.get_at(REF(s)).computed.error.as_ref().map(|e| e.kind);
+        assert_eq!(kind("B1"), Some(CellError::Div0));
+        assert_eq!(kind("B2"), Some(CellError::Div0));
+        assert!(state.get_at(REF("B2")).computed.is_computed);
+    }
+
+    #[test]
+    fn test_bake_errors() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("=1 / 0".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=A1 + 1".to_string()));
+        state.set_at(REF("A3"), DisplayCell::new("=NOPE(1)".to_string()));
+        state.set_at(REF("A4"), DisplayCell::new("=1 +".to_string()));
+        state.set_at(REF("A5"), DisplayCell::new("=\"a\" * 2".to_string()));
+        state.set_at(REF("A6"), DisplayCell::new("=SQRT(-1)".to_string()));
+        state.set_at(REF("A7"), DisplayCell::new("=#REF! + 1".to_string()));
+        state.set_at(REF("A8"), DisplayCell::new("=Z99 + 1".to_string()));
+        state.set_at(REF("A9"), DisplayCell::new("=POW(2)".to_string()));
+        state.set_at(
+            REF("A10"),
+            DisplayCell::new("=SUM(1e308, 1e308)".to_string()),
+        );
+        state.set_at(
+            REF("A11"),
+            DisplayCell::new("=AVG(1e308, 1e308)".to_string()),
+        );
+        state.set_at(REF("A12"), DisplayCell::new("=ROUND(1, 400)".to_string()));
+        state.set_at(REF("A13"), DisplayCell::new("=ROUND(1, -400)".to_string()));
+        state.set_at(REF("B1"), DisplayCell::new("=ISERROR(A2)".to_string()));
+        state.set_at(REF("B2"), DisplayCell::new("=ERROR.TYPE(A2)".to_string()));
+        state.set_at(REF("B3"), DisplayCell::new("=ISERROR(1)".to_string()));
+        bake(&mut state);
+
+        let kind = |s: &str| state.get_at(REF(s)).computed.error.as_ref().map(|e| e.kind);
+
+        assert_eq!(kind("A1"), Some(CellError::Div0));
+        assert_eq!(kind("A3"), Some(CellError::Name));
+        assert_eq!(kind("A4"), Some(CellError::Parse));
+        assert_eq!(kind("A5"), Some(CellError::Value));
+        assert_eq!(kind("A6"), Some(CellError::Num));
+        assert_eq!(kind("A7"), Some(CellError::Ref));
+        assert_eq!(kind("A9"), Some(CellError::Value));
+        assert_eq!(kind("A10"), Some(CellError::Num));
+        assert_eq!(kind("A11"), Some(CellError::Num));
+
+        // rounding to far more digits than a number holds still gives a number
+        assert_eq!(state.get_at(REF("A12")).computed.display, "1");
+        assert_eq!(state.get_at(REF("A13")).computed.display, "0");
+
+        // an empty cell reads as 0
+        assert_eq!(state.get_at(REF("A8")).computed.display, "1");
+
+        // errors keep the cell they came from as they propagate
+        let a2 = state.get_at(REF("A2"));
+        assert_eq!(a2.computed.display, "#DIV/0!");
+        assert_eq!(
+            a2.computed.error,
+            Some(ComputeError::new(CellError::Div0, "Divide by zero").at(REF("A1")))
+        );
+        assert_eq!(
+            a2.computed.error.as_ref().unwrap().to_string(),
+            "#DIV/0! Divide by zero @ A1"
+        );
+
+        let value = |s: &str| state.get_at(REF(s)).computed.value.clone();
+        assert_eq!(value("B1"), Some(Value::Bool(true)));
+        assert_eq!(value("B2"), Some(Value::Number(2.0)));
+        assert_eq!(value("B3"), Some(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_bake_precision() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("=0.1 + 0.2".to_string()));
+        state.set_at(REF("A2"), DisplayCell::new("=16777216 + 0.01".to_string()));
+        bake(&mut state);
+
+        assert_eq!(state.get_at(REF("A1")).computed.display, "0.3");
+        assert_eq!(state.get_at(REF("A2")).computed.display, "16777216.01");
+        assert_eq!(state.get_at(REF("A2")).computed.display_in(7), "1.678e7");
+    }
+
+    #[test]
+    fn test_bake_incremental() {
+        let mut state = State::blank();
+        state.set_at(REF("A1"), DisplayCell::new("1".to_string()));
+        state.set_at(REF("B1"), DisplayCell::new("=A1 * 2".to_string()));
+        state.set_at(REF("C1"), DisplayCell::new("5".to_string()));
+        state.set_at(REF("D1"), DisplayCell::new("=SUM(A:A)".to_string()));
+        bake(&mut state);
+
+        // cells that do not depend on an edit are left alone
+        state.computation_mut(REF("C1")).unwrap().display = "untouched".to_string();
+
+        state.set_at(REF("A1"), DisplayCell::new("3".to_string()));
+        bake(&mut state);
+
+        let value = |state: &State, s: &str| state.get_at(REF(s)).computed.value.clone();
+        assert_eq!(value(&state, "B1"), Some(Value::Number(6.0)));
+        assert_eq!(value(&state, "D1"), Some(Value::Number(3.0)));
+        assert_eq!(state.get_at(REF("C1")).computed.display, "untouched");
+
+        // new cells inside a range are picked up
+        state.set_at(REF("A7"), DisplayCell::new("10".to_string()));
+        bake(&mut state);
+        assert_eq!(value(&state, "D1"), Some(Value::Number(13.0)));
+
+        // so are edits made while typing
+        state.edit_at(REF("A7"), |_| DisplayCell::new("".to_string()));
+        state.edit_at(REF("A1"), |_| DisplayCell::new("".to_string()));
+        bake(&mut state);
+        assert_eq!(value(&state, "D1"), Some(Value::Number(0.0)));
+        assert_eq!(value(&state, "B1"), Some(Value::Number(0.0)));
+        assert_eq!(state.get_at(REF("A1")).computed.display, "");
+    }
+
+    #[test]
+    fn test_bake_long_chain() {
+        let mut state = State::blank();
+        state.set_at((0, 0), DisplayCell::new("1".to_string()));
+        for r in 1..5000 {
+            let formula = format!("=A{} + 1", r);
+            state.set_at((r, 0), DisplayCell::new(formula));
+        }
+        bake(&mut state);
+
+        assert_eq!(
+            state.get_at((4999, 0)).computed.value,
+            Some(Value::Number(5000.0))
+        );
+
+        state.set_at((0, 0), DisplayCell::new("0".to_string()));
+        bake(&mut state);
+
+        assert_eq!(
+            state.get_at((4999, 0)).computed.value,
+            Some(Value::Number(4999.0))
+        );
     }
 }
@@ -0,0 +1,139 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+type Address = (u16, u16);
+
+/// The strongly connected components of a graph given as a map from each cell to the
+/// cells that depend on it (Tarjan's algorithm). Every cell appears in exactly one
+/// component; a component is a cycle if it has more than one member or a self-loop.
+pub fn strongly_connected(edges: &HashMap<Address, Vec<Address>>) -> Vec<Vec<Address>> {
+    let mut index = 0;
+    let mut indices: HashMap<Address, usize> = HashMap::new();
+    let mut lowlinks: HashMap<Address, usize> = HashMap::new();
+    let mut stack: Vec<Address> = Vec::new();
+    let mut on_stack: HashSet<Address> = HashSet::new();
+    let mut components = Vec::new();
+
+    let mut nodes: Vec<&Address> = edges.keys().collect();
+    nodes.sort();
+
+    for &root in nodes {
+        if indices.contains_key(&root) {
+            continue;
+        }
+
+        // explicit call stack of (node, next edge to visit) so long chains can't overflow
+        let mut call_stack = vec![(root, 0)];
+        indices.insert(root, index);
+        lowlinks.insert(root, index);
+        index += 1;
+        stack.push(root);
+        on_stack.insert(root);
+
+        while let Some((node, edge)) = call_stack.last_mut() {
+            let node = *node;
+            let successors = edges.get(&node).map_or(&[][..], |e| e.as_slice());
+
+            if *edge < successors.len() {
+                let next = successors[*edge];
+                *edge += 1;
+
+                if let Entry::Vacant(entry) = indices.entry(next) {
+                    entry.insert(index);
+                    lowlinks.insert(next, index);
+                    index += 1;
+                    stack.push(next);
+                    on_stack.insert(next);
+                    call_stack.push((next, 0));
+                } else if on_stack.contains(&next) {
+                    let lowlink = lowlinks[&node].min(indices[&next]);
+                    lowlinks.insert(node, lowlink);
+                }
+                continue;
+            }
+
+            call_stack.pop();
+            if let Some((parent, _)) = call_stack.last() {
+                let lowlink = lowlinks[parent].min(lowlinks[&node]);
+                lowlinks.insert(*parent, lowlink);
+            }
+
+            if lowlinks[&node] == indices[&node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack.remove(&member);
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort();
+                components.push(component);
+            }
+        }
+    }
+
+    components
+}
+
+/// The cells that form cycles, mapped to all members of their cycle
+pub fn find_cycles(edges: &HashMap<Address, Vec<Address>>) -> HashMap<Address, Vec<Address>> {
+    let mut cycles = HashMap::new();
+
+    for component in strongly_connected(edges) {
+        let addr = component[0];
+        let is_cycle = component.len() > 1 || edges[&addr].contains(&addr);
+        if !is_cycle {
+            continue;
+        }
+
+        for member in component.iter() {
+            cycles.insert(*member, component.clone());
+        }
+    }
+
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strongly_connected() {
+        // 0 -> 1 -> 2 -> 0, 2 -> 3, 4 -> 4
+        let edges = HashMap::from([
+            ((0, 0), vec![(0, 1)]),
+            ((0, 1), vec![(0, 2)]),
+            ((0, 2), vec![(0, 0), (0, 3)]),
+            ((0, 3), vec![]),
+            ((0, 4), vec![(0, 4)]),
+        ]);
+
+        let mut components = strongly_connected(&edges);
+        components.sort();
+        assert_eq!(
+            components,
+            vec![vec![(0, 0), (0, 1), (0, 2)], vec![(0, 3)], vec![(0, 4)]]
+        );
+
+        let cycles = find_cycles(&edges);
+        assert_eq!(cycles.len(), 4);
+        assert_eq!(cycles[&(0, 1)], vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(cycles[&(0, 4)], vec![(0, 4)]);
+        assert!(!cycles.contains_key(&(0, 3)));
+    }
+
+    #[test]
+    fn test_strongly_connected_long_chain() {
+        let mut edges: HashMap<Address, Vec<Address>> = HashMap::new();
+        for r in 0..50_000 {
+            edges.insert((r, 0), vec![(r + 1, 0)]);
+        }
+        edges.insert((50_000, 0), vec![]);
+
+        assert_eq!(strongly_connected(&edges).len(), 50_001);
+        assert!(find_cycles(&edges).is_empty());
+    }
+}
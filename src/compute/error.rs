@@ -0,0 +1,116 @@
+use crate::compute::node::format_reference;
+use std::fmt;
+
+/// The kinds of error a cell can be in, each shown in the grid by its code (`#REF!`)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CellError {
+    /// Division by zero, or an average of no values
+    Div0,
+    /// A reference to a cell that was deleted or is off the grid
+    Ref,
+    /// A function that doesn't exist
+    Name,
+    /// A value of the wrong type, or the wrong number of arguments
+    Value,
+    /// Part of a cycle of cells that refer to each other
+    Cycle,
+    /// Read from a cell in a cycle, without being part of it
+    DependsOnCycle,
+    /// A formula that can't be parsed
+    Parse,
+    /// A result that is not a finite number
+    Num,
+    /// A cycle that didn't settle within its iterations
+    Converge,
+}
+
+impl CellError {
+    /// The short code shown in the grid
+    pub fn code(&self) -> &'static str {
+        match self {
+            CellError::Div0 => "#DIV/0!",
+            CellError::Ref => "#REF!",
+            CellError::Name => "#NAME?",
+            CellError::Value => "#VALUE!",
+            CellError::Cycle => "#CYCLE!",
+            CellError::DependsOnCycle => "#DEPCY!",
+            CellError::Parse => "#PARSE!",
+            CellError::Num => "#NUM!",
+            CellError::Converge => "#CONV!",
+        }
+    }
+
+    /// The number ERROR.TYPE returns for this kind of error
+    pub fn type_number(&self) -> f64 {
+        match self {
+            CellError::Div0 => 2.0,
+            CellError::Value => 3.0,
+            CellError::Ref => 4.0,
+            CellError::Name => 5.0,
+            CellError::Num => 6.0,
+            CellError::Cycle => 8.0,
+            CellError::Parse => 9.0,
+            CellError::Converge => 10.0,
+            CellError::DependsOnCycle => 11.0,
+        }
+    }
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// An error raised while parsing or computing a cell, along with the cell it
+/// originated from
+#[derive(Debug, PartialEq, Clone)]
+pub struct ComputeError {
+    /// What went wrong, as shown in the grid
+    pub kind: CellError,
+    /// What went wrong, in words
+    pub message: String,
+    /// The cell the error was raised in, if it's known
+    pub origin: Option<(u16, u16)>,
+}
+
+impl ComputeError {
+    /// An error not yet tied to the cell it was raised in
+    pub fn new(kind: CellError, message: impl Into<String>) -> Self {
+        ComputeError {
+            kind,
+            message: message.into(),
+            origin: None,
+        }
+    }
+
+    /// Record where the error was raised, unless it was passed on from another cell
+    pub fn at(mut self, addr: (u16, u16)) -> Self {
+        self.origin.get_or_insert(addr);
+        self
+    }
+}
+
+// Plain string errors come from the parser
+impl From<String> for ComputeError {
+    fn from(message: String) -> Self {
+        ComputeError::new(CellError::Parse, message)
+    }
+}
+
+impl fmt::Display for ComputeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.origin {
+            Some(origin) => write!(
+                f,
+                "{} {} @ {}",
+                self.kind,
+                self.message,
+                format_reference(origin)
+            ),
+            None => write!(f, "{} {}", self.kind, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ComputeError {}
@@ -0,0 +1,285 @@
+use crate::compute::error::{CellError, ComputeError};
+use crate::compute::node::Node;
+use crate::compute::value::{round_to_precision, Value};
+use crate::state::State;
+
+/// A function that can be called from a formula, such as `SUM(A1:A3)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Function {
+    /// The total of its values
+    Sum,
+    /// The mean of its values, also called as AVERAGE
+    Avg,
+    /// The first value raised to the power of the second, also called as POWER
+    Pow,
+    /// The smallest of its values, or 0 if there are none
+    Min,
+    /// The largest of its values, or 0 if there are none
+    Max,
+    /// How many numbers it was given
+    Count,
+    /// The value without its sign
+    Abs,
+    /// The square root of a value that isn't negative
+    Sqrt,
+    /// A value rounded to a number of decimals, or to whole numbers if none is given
+    Round,
+    /// The second value if the first is TRUE, otherwise the third (or FALSE)
+    If,
+    /// The first value, or the second if computing the first gives an error
+    IfError,
+    /// Whether all of its values are TRUE
+    And,
+    /// Whether any of its values is TRUE
+    Or,
+    /// The opposite of a boolean
+    Not,
+    /// Whether computing its value gives an error
+    IsError,
+    /// The number for the kind of error its value gives, as listed by CellError
+    ErrorType,
+}
+
+// name, function, min args, max args (None = variadic)
+const REGISTRY: &[(&str, Function, usize, Option<usize>)] = &[
+    ("SUM", Function::Sum, 1, None),
+    ("AVG", Function::Avg, 1, None),
+    ("AVERAGE", Function::Avg, 1, None),
+    ("POW", Function::Pow, 2, Some(2)),
+    ("POWER", Function::Pow, 2, Some(2)),
+    ("MIN", Function::Min, 1, None),
+    ("MAX", Function::Max, 1, None),
+    ("COUNT", Function::Count, 1, None),
+    ("ABS", Function::Abs, 1, Some(1)),
+    ("SQRT", Function::Sqrt, 1, Some(1)),
+    ("ROUND", Function::Round, 1, Some(2)),
+    ("IF", Function::If, 2, Some(3)),
+    ("IFERROR", Function::IfError, 2, Some(2)),
+    ("AND", Function::And, 1, None),
+    ("OR", Function::Or, 1, None),
+    ("NOT", Function::Not, 1, Some(1)),
+    ("ISERROR", Function::IsError, 1, Some(1)),
+    ("ERROR.TYPE", Function::ErrorType, 1, Some(1)),
+];
+
+impl Function {
+    /// Look up a function by its (case-insensitive) name
+    pub fn from_name(name: &str) -> Option<Function> {
+        let name = name.to_uppercase();
+        REGISTRY
+            .iter()
+            .find(|(n, ..)| *n == name)
+            .map(|(_, function, ..)| *function)
+    }
+
+    /// The name a formula calls the function by
+    pub fn name(&self) -> &'static str {
+        REGISTRY
+            .iter()
+            .find(|(_, function, ..)| function == self)
+            .map(|(name, ..)| *name)
+            .unwrap()
+    }
+
+    /// Check that the function can be called with this many arguments
+    pub fn check_arity(&self, count: usize) -> Result<(), ComputeError> {
+        let (_, _, min, max) = REGISTRY.iter().find(|(_, f, ..)| f == self).unwrap();
+
+        if count < *min {
+            return Err(ComputeError::new(
+                CellError::Value,
+                format!(
+                    "{} expects at least {} argument(s), got {}",
+                    self.name(),
+                    min,
+                    count
+                ),
+            ));
+        }
+
+        match max {
+            Some(max) if count > *max => Err(ComputeError::new(
+                CellError::Value,
+                format!(
+                    "{} expects at most {} argument(s), got {}",
+                    self.name(),
+                    max,
+                    count
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Aggregates take any number of values, so ranges may be spread into them
+    pub fn is_aggregate(&self) -> bool {
+        matches!(
+            self,
+            Function::Sum
+                | Function::Avg
+                | Function::Min
+                | Function::Max
+                | Function::Count
+                | Function::And
+                | Function::Or
+        )
+    }
+
+    /// Arguments are computed only when needed, so the branch IF does not take (or
+    /// the arguments after AND/OR have short-circuited) can not raise errors
+    pub fn call(&self, args: &[Node], state: &State) -> Result<Value, ComputeError> {
+        let mut args = args.iter();
+
+        match self {
+            Function::If => {
+                let condition = args.next().unwrap().compute(state)?.as_bool()?;
+                let (then, otherwise) = (args.next().unwrap(), args.next());
+
+                match (condition, otherwise) {
+                    (true, _) => then.compute(state),
+                    (false, Some(otherwise)) => otherwise.compute(state),
+                    (false, None) => Ok(Value::Bool(false)),
+                }
+            }
+            Function::IfError => {
+                let (value, fallback) = (args.next().unwrap(), args.next().unwrap());
+                value.compute(state).or_else(|_| fallback.compute(state))
+            }
+            Function::And | Function::Or => {
+                // AND stops at the first FALSE, OR at the first TRUE
+                let stop_at = *self == Function::Or;
+
+                for arg in args {
+                    for value in arg.spread(state)? {
+                        if value.as_bool()? == stop_at {
+                            return Ok(Value::Bool(stop_at));
+                        }
+                    }
+                }
+
+                Ok(Value::Bool(!stop_at))
+            }
+            Function::IsError => Ok(Value::Bool(args.next().unwrap().compute(state).is_err())),
+            Function::ErrorType => match args.next().unwrap().compute(state) {
+                Err(err) => Ok(Value::Number(err.kind.type_number())),
+                Ok(_) => Err(ComputeError::new(
+                    CellError::Value,
+                    "ERROR.TYPE of a value that is not an error",
+                )),
+            },
+            Function::Not => Ok(Value::Bool(
+                !args.next().unwrap().compute(state)?.as_bool()?,
+            )),
+            _ => {
+                let mut values = Vec::new();
+                for arg in args {
+                    for value in arg.spread(state)? {
+                        values.push(value.as_number()?);
+                    }
+                }
+
+                // sums and averages can overflow, as arithmetic can
+                match self.apply(values)? {
+                    result if result.is_finite() => Ok(Value::Number(result)),
+                    _ => Err(ComputeError::new(
+                        CellError::Num,
+                        format!("{} result is not a number", self.name()),
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Apply a numeric function to the values of its arguments
+    pub fn apply(&self, args: Vec<f64>) -> Result<f64, ComputeError> {
+        match self {
+            Function::Sum => Ok(args.iter().sum()),
+            Function::Avg if args.is_empty() => {
+                Err(ComputeError::new(CellError::Div0, "AVG of no values"))
+            }
+            Function::Avg => Ok(args.iter().sum::<f64>() / args.len() as f64),
+            Function::Pow => {
+                let result = args[0].powf(args[1]);
+                if result.is_finite() {
+                    Ok(result)
+                } else {
+                    Err(ComputeError::new(
+                        CellError::Num,
+                        "POW result is not a number",
+                    ))
+                }
+            }
+            Function::Min | Function::Max if args.is_empty() => Ok(0.0),
+            Function::Min => Ok(args.into_iter().fold(f64::INFINITY, f64::min)),
+            Function::Max => Ok(args.into_iter().fold(f64::NEG_INFINITY, f64::max)),
+            Function::Count => Ok(args.len() as f64),
+            Function::Abs => Ok(args[0].abs()),
+            Function::Sqrt if args[0] < 0.0 => Err(ComputeError::new(
+                CellError::Num,
+                "SQRT of a negative number",
+            )),
+            Function::Sqrt => Ok(args[0].sqrt()),
+            Function::Round => {
+                // past these 10^digits is infinite or 0
+                let digits = args.get(1).copied().unwrap_or(0.0).trunc();
+                let factor = 10f64.powf(digits.clamp(-308.0, 308.0));
+                // drop float noise first, so 2.675 rounds up rather than down
+                let scaled = round_to_precision(args[0] * factor);
+                if !scaled.is_finite() {
+                    // more digits than the number has, so there's nothing to round
+                    return Ok(args[0]);
+                }
+                Ok(scaled.round() / factor)
+            }
+            _ => unreachable!("{} is not a numeric function", self.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Function::from_name("SUM"), Some(Function::Sum));
+        assert_eq!(Function::from_name("sum"), Some(Function::Sum));
+        assert_eq!(Function::from_name("AVERAGE"), Some(Function::Avg));
+        assert_eq!(Function::from_name("NOPE"), None);
+    }
+
+    #[test]
+    fn test_check_arity() {
+        assert_eq!(Function::Sum.check_arity(5), Ok(()));
+        assert_eq!(
+            Function::Sum.check_arity(0),
+            Err(ComputeError::new(
+                CellError::Value,
+                "SUM expects at least 1 argument(s), got 0"
+            ))
+        );
+        assert_eq!(
+            Function::Pow.check_arity(3),
+            Err(ComputeError::new(
+                CellError::Value,
+                "POW expects at most 2 argument(s), got 3"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(Function::Sum.apply(vec![1.0, 2.0, 3.0]), Ok(6.0));
+        assert_eq!(Function::Avg.apply(vec![1.0, 2.0, 3.0]), Ok(2.0));
+        assert_eq!(Function::Pow.apply(vec![2.0, 3.0]), Ok(8.0));
+        assert_eq!(Function::Min.apply(vec![4.0, -2.0, 3.0]), Ok(-2.0));
+        assert_eq!(Function::Max.apply(vec![4.0, -2.0, 3.0]), Ok(4.0));
+        assert_eq!(Function::Round.apply(vec![2.345, 1.0]), Ok(2.3));
+        assert_eq!(Function::Round.apply(vec![2.675, 2.0]), Ok(2.68));
+        assert_eq!(Function::Round.apply(vec![1234.5, -2.0]), Ok(1200.0));
+        assert_eq!(Function::Round.apply(vec![1.5, 400.0]), Ok(1.5));
+        assert_eq!(Function::Round.apply(vec![1.5, -400.0]), Ok(0.0));
+        assert_eq!(Function::Round.apply(vec![1e300, 300.0]), Ok(1e300));
+        assert!(Function::Sqrt.apply(vec![-1.0]).is_err());
+    }
+}
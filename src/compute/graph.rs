@@ -0,0 +1,220 @@
+use crate::compute::error::ComputeError;
+use crate::compute::node::{Node, Range};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+
+type Address = (u16, u16);
+
+/// Parsed formulas and the references between cells, kept between bakes so that only
+/// the cells that changed (and whatever depends on them) have to be recomputed
+pub struct DependencyGraph {
+    // parsed contents of every non-empty cell
+    formulas: HashMap<Address, Result<Node, ComputeError>>,
+    // the areas each cell reads from
+    precedents: HashMap<Address, Vec<Range>>,
+    // cells that read a single cell directly
+    dependents: HashMap<Address, HashSet<Address>>,
+    // cells that read a multi-cell range, which are found by containment
+    range_dependents: HashMap<Address, Vec<Range>>,
+    // the cells in `range_dependents`, filed under each column (or row, whichever
+    // there are fewer of) their ranges cover, so a lookup only checks nearby ranges
+    column_buckets: HashMap<u16, HashSet<Address>>,
+    row_buckets: HashMap<u16, HashSet<Address>>,
+    // cells edited since the last bake
+    dirty: HashSet<Address>,
+    // when set, the whole sheet is re-parsed on the next bake
+    stale: bool,
+}
+
+impl Default for DependencyGraph {
+    fn default() -> Self {
+        DependencyGraph::new()
+    }
+}
+
+impl DependencyGraph {
+    /// An empty graph, which is stale until its first bake
+    pub fn new() -> Self {
+        DependencyGraph {
+            formulas: HashMap::new(),
+            precedents: HashMap::new(),
+            dependents: HashMap::new(),
+            range_dependents: HashMap::new(),
+            column_buckets: HashMap::new(),
+            row_buckets: HashMap::new(),
+            dirty: HashSet::new(),
+            stale: true,
+        }
+    }
+
+    /// Note that a cell was edited, so it's re-parsed on the next bake
+    pub fn mark_dirty(&mut self, addr: Address) {
+        self.dirty.insert(addr);
+    }
+
+    /// Re-parse and recompute the whole sheet on the next bake
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Take the cells edited since the last bake. Returns None if everything is stale.
+    pub fn take_dirty(&mut self) -> Option<HashSet<Address>> {
+        let dirty = std::mem::take(&mut self.dirty);
+
+        if self.stale {
+            *self = DependencyGraph::new();
+            self.stale = false;
+            return None;
+        }
+
+        Some(dirty)
+    }
+
+    /// The parsed contents of a cell, if it holds anything
+    pub fn formula(&self, addr: Address) -> Option<&Result<Node, ComputeError>> {
+        self.formulas.get(&addr)
+    }
+
+    /// Every cell that reads from `addr`, directly or through a range
+    pub fn dependents(&self, addr: Address) -> Vec<Address> {
+        let mut dependents: Vec<Address> = self
+            .dependents
+            .get(&addr)
+            .map(|d| d.iter().copied().collect())
+            .unwrap_or_default();
+
+        let candidates = [
+            self.column_buckets.get(&addr.1),
+            self.row_buckets.get(&addr.0),
+        ];
+        let mut found = HashSet::new();
+
+        for dependent in candidates.into_iter().flatten().flatten() {
+            let ranges = &self.range_dependents[dependent];
+            if ranges.iter().any(|range| range.contains(addr)) && found.insert(*dependent) {
+                dependents.push(*dependent);
+            }
+        }
+
+        dependents
+    }
+
+    /// Store the parsed contents of a cell, replacing the edges of whatever it held before
+    pub fn set_formula(&mut self, addr: Address, formula: Result<Node, ComputeError>) {
+        self.remove(addr);
+
+        let precedents = match &formula {
+            Ok(node) => node.get_references().unwrap_or_default(),
+            Err(_) => vec![],
+        };
+
+        for range in precedents.iter() {
+            if range.start == range.end {
+                self.dependents.entry(range.start).or_default().insert(addr);
+            } else {
+                self.range_dependents.entry(addr).or_default().push(*range);
+
+                let (buckets, span) =
+                    bucket(range, &mut self.column_buckets, &mut self.row_buckets);
+                for idx in span {
+                    buckets.entry(idx).or_default().insert(addr);
+                }
+            }
+        }
+
+        self.precedents.insert(addr, precedents);
+        self.formulas.insert(addr, formula);
+    }
+
+    /// Forget a cell that was emptied, along with the edges to what it read from
+    pub fn remove(&mut self, addr: Address) {
+        self.formulas.remove(&addr);
+        for range in self.range_dependents.remove(&addr).unwrap_or_default() {
+            let (buckets, span) = bucket(&range, &mut self.column_buckets, &mut self.row_buckets);
+            for idx in span {
+                if let Some(bucket) = buckets.get_mut(&idx) {
+                    bucket.remove(&addr);
+                    if bucket.is_empty() {
+                        buckets.remove(&idx);
+                    }
+                }
+            }
+        }
+
+        for range in self.precedents.remove(&addr).unwrap_or_default() {
+            if range.start != range.end {
+                continue;
+            }
+
+            if let Some(dependents) = self.dependents.get_mut(&range.start) {
+                dependents.remove(&addr);
+                if dependents.is_empty() {
+                    self.dependents.remove(&range.start);
+                }
+            }
+        }
+    }
+}
+
+// The buckets a range is filed under, and which of them it covers: its columns, unless
+// it spans more columns than rows
+fn bucket<'a>(
+    range: &Range,
+    columns: &'a mut HashMap<u16, HashSet<Address>>,
+    rows: &'a mut HashMap<u16, HashSet<Address>>,
+) -> (&'a mut HashMap<u16, HashSet<Address>>, RangeInclusive<u16>) {
+    if range.end.1 - range.start.1 <= range.end.0 - range.start.0 {
+        (columns, range.start.1..=range.end.1)
+    } else {
+        (rows, range.start.0..=range.end.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::parse::parse;
+
+    #[test]
+    fn test_dependents() {
+        let mut graph = DependencyGraph::new();
+        graph.set_formula((0, 1), parse("A1 * 2"));
+        graph.set_formula((0, 2), parse("SUM(A:A)"));
+        graph.set_formula((0, 3), parse("B1 + A1"));
+
+        let mut dependents = graph.dependents((0, 0));
+        dependents.sort();
+        assert_eq!(dependents, vec![(0, 1), (0, 2), (0, 3)]);
+
+        assert_eq!(graph.dependents((5, 0)), vec![(0, 2)]);
+        assert_eq!(graph.dependents((0, 1)), vec![(0, 3)]);
+
+        // replacing a formula drops its old edges
+        graph.set_formula((0, 3), parse("5"));
+        assert_eq!(graph.dependents((0, 1)), vec![]);
+
+        // ranges are found whichever way they are filed
+        graph.set_formula((0, 4), parse("SUM(B2:Z3)"));
+        graph.set_formula((0, 5), parse("SUM(C1:D9) + SUM(C2:C3)"));
+        assert_eq!(graph.dependents((2, 25)), vec![(0, 4)]);
+        assert_eq!(graph.dependents((2, 2)).len(), 2);
+        assert_eq!(graph.dependents((4, 4)), vec![]);
+        graph.remove((0, 4));
+        assert_eq!(graph.dependents((2, 25)), vec![]);
+
+        graph.remove((0, 2));
+        assert_eq!(graph.dependents((5, 0)), vec![]);
+        assert_eq!(graph.dependents((0, 0)), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_take_dirty() {
+        let mut graph = DependencyGraph::new();
+        graph.mark_dirty((1, 1));
+        assert_eq!(graph.take_dirty(), None);
+
+        graph.mark_dirty((1, 1));
+        assert_eq!(graph.take_dirty(), Some(HashSet::from([(1, 1)])));
+        assert_eq!(graph.take_dirty(), Some(HashSet::new()));
+    }
+}
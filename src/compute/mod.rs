@@ -1,5 +1,21 @@
+//! Parsing and computing formulas.
+
 mod bake;
+mod cycles;
+mod error;
+mod function;
+mod graph;
 mod node;
 mod parse;
+mod value;
 
-pub use bake::bake;
+pub use bake::{bake, Iteration};
+pub use error::{CellError, ComputeError};
+pub use function::Function;
+pub use graph::DependencyGraph;
+pub use node::{
+    format_column, format_reference, parse_reference, resolve_range, resolve_reference, Axis,
+    BinaryOp, CellRef, Node, Range, Splice, UnaryOp,
+};
+pub use parse::{is_formula, parse, parse_cell, shift_cell, splice_cell};
+pub use value::{fit_number, Value};
@@ -1,125 +1,607 @@
+use crate::compute::error::{CellError, ComputeError};
+use crate::compute::function::Function;
+use crate::compute::value::Value;
 use crate::state::State;
 use regex::Regex;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::OnceLock;
 
+/// A parsed formula, as a tree of the values and operations it's made of
 #[derive(Debug, PartialEq)]
 pub enum Node {
-    Literal(f32),
-    // UnaryOp(UnaryOp, Node),
+    /// A number written out in the formula
+    Literal(f64),
+    /// TRUE or FALSE
+    Boolean(bool),
+    /// Text written in double quotes
+    Text(String),
+    /// An operator in front of a value, such as `-A1`
+    UnaryOp(UnaryOp, Box<Node>),
+    /// An operator between two values, such as `A1 * 2`
     BinaryOp(BinaryOp, Box<Node>, Box<Node>),
-    // Function(Function, Vec<Node>),
-    Reference(String),
+    /// A call to a function with its arguments
+    Function(Function, Vec<Node>),
+    /// The value of a single cell
+    Reference(CellRef),
+    /// A range of cells as written, such as `A1:B3`
+    Range(String),
+    /// A reference that was moved off the grid, written #REF!
+    InvalidReference,
 }
 
 impl Node {
-    pub fn get_references(&self) -> Vec<&String> {
+    /// All areas this node reads from, with a single reference as a 1x1 range
+    pub fn get_references(&self) -> Result<Vec<Range>, ComputeError> {
         match self {
-            Node::Reference(reference) => vec![reference],
+            Node::Reference(reference) => Ok(vec![Range {
+                start: reference.addr,
+                end: reference.addr,
+            }]),
+            Node::Range(reference) => Ok(vec![resolve_range(reference)?]),
+            Node::UnaryOp(_, node) => node.get_references(),
             Node::BinaryOp(_, left, right) => {
-                let mut left_refs = left.get_references();
-                let mut right_refs = right.get_references();
+                let mut left_refs = left.get_references()?;
+                let mut right_refs = right.get_references()?;
 
                 left_refs.append(&mut right_refs);
 
-                left_refs
+                Ok(left_refs)
             }
-            _ => vec![],
+            Node::Function(_, args) => {
+                let mut refs = Vec::new();
+                for arg in args {
+                    refs.append(&mut arg.get_references()?);
+                }
+                Ok(refs)
+            }
+            _ => Ok(vec![]),
         }
     }
 
-    pub fn compute(self, state: &State) -> Result<f32, String> {
+    /// The value of the formula, read from the computed values of the cells it refers to
+    pub fn compute(&self, state: &State) -> Result<Value, ComputeError> {
         match self {
-            Node::Literal(num) => Ok(num),
+            Node::Literal(num) => Ok(Value::Number(*num)),
+            Node::Boolean(b) => Ok(Value::Bool(*b)),
+            Node::Text(text) => Ok(Value::Text(text.clone())),
+            Node::UnaryOp(op, node) => {
+                let x = node.compute(state)?.as_number()?;
+
+                Ok(Value::Number(match op {
+                    UnaryOp::Negative => -x,
+                    UnaryOp::Positive => x,
+                }))
+            }
             Node::BinaryOp(op, left, right) => {
-                let left_result = (*left).compute(state);
-                let right_result = (*right).compute(state);
+                // the right side is never evaluated if the left side fails
+                let op = *op;
+                let left = left.compute(state)?;
+                let right = right.compute(state)?;
 
-                let Ok(x) = left_result else {
-                    return Err(left_result.unwrap_err());
-                };
+                if op.is_comparison() {
+                    let ordering = left.compare(&right);
 
-                let Ok(y) = right_result else {
-                    return Err(right_result.unwrap_err());
-                };
+                    return Ok(Value::Bool(match op {
+                        BinaryOp::Equal => ordering == Some(Ordering::Equal),
+                        BinaryOp::NotEqual => ordering != Some(Ordering::Equal),
+                        BinaryOp::Less => ordering == Some(Ordering::Less),
+                        BinaryOp::LessEqual => ordering.is_some_and(|o| o.is_le()),
+                        BinaryOp::Greater => ordering == Some(Ordering::Greater),
+                        BinaryOp::GreaterEqual => ordering.is_some_and(|o| o.is_ge()),
+                        _ => unreachable!(),
+                    }));
+                }
 
-                if y == 0.0 && op == BinaryOp::Divide {
-                    return Err("Divide by 0 error".to_string());
+                if op == BinaryOp::Concat {
+                    return Ok(Value::Text(format!("{}{}", left, right)));
                 }
 
-                Ok(match op {
+                let (x, y) = (left.as_number()?, right.as_number()?);
+
+                if y == 0.0 && (op == BinaryOp::Divide || op == BinaryOp::Modulo) {
+                    return Err(ComputeError::new(CellError::Div0, "Divide by zero"));
+                }
+
+                let result = match op {
                     BinaryOp::Add => x + y,
                     BinaryOp::Subtract => x - y,
                     BinaryOp::Multiply => x * y,
                     BinaryOp::Divide => x / y,
-                })
-            }
-            Node::Reference(reference) => {
-                let addr_result = resolve_reference(&reference);
-                let Ok(addr) = addr_result else {
-                    return Err(addr_result.unwrap_err());
+                    // the result takes the sign of the divisor
+                    BinaryOp::Modulo => x - y * (x / y).floor(),
+                    BinaryOp::Power => x.powf(y),
+                    _ => unreachable!(),
                 };
 
-                let cell = state.get_at(addr);
-
-                if cell.value.is_empty() {
-                    return Err(format!("Error: Empty value @ {}", reference));
+                if !result.is_finite() {
+                    return Err(ComputeError::new(CellError::Num, "Result is not a number"));
                 }
 
-                if !cell.computed.is_computed {
-                    panic!("REFERENCE IS NOT COMPUTED @ {} {:?}", reference, addr);
-                }
+                Ok(Value::Number(result))
+            }
+            Node::Function(function, args) => function.call(args, state),
+            Node::Reference(reference) => {
+                let addr = reference.addr;
 
-                if cell.computed.error {
-                    return Err(format!(
-                        "Err @ {}: \"{}\"",
-                        reference, cell.computed.display
-                    ));
+                // an empty cell reads as a blank, which counts as 0
+                if state.get_at(addr).value.is_empty() {
+                    return Ok(Value::Number(0.0));
                 }
 
-                Ok(cell.computed.value.unwrap())
+                cell_value(state, addr)
             }
+            Node::InvalidReference => Err(ComputeError::new(
+                CellError::Ref,
+                "Reference was moved off the grid",
+            )),
+            Node::Range(reference) => Err(ComputeError::new(
+                CellError::Value,
+                format!(
+                    "Range {} can only be used as a function argument",
+                    reference
+                ),
+            )),
         }
     }
 }
 
+impl Node {
+    /// Compute a function argument, spreading a range into the values of its non-empty
+    /// cells. Text in a range is skipped rather than passed on.
+    pub fn spread(&self, state: &State) -> Result<Vec<Value>, ComputeError> {
+        let Node::Range(reference) = self else {
+            return Ok(vec![self.compute(state)?]);
+        };
+
+        let mut values = Vec::new();
+        for addr in resolve_range(reference)?.cells(state) {
+            if state.get_at(addr).value.is_empty() {
+                continue;
+            }
+
+            match cell_value(state, addr)? {
+                Value::Text(_) => {}
+                value => values.push(value),
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+// Read the computed value of a (non-empty) cell
+fn cell_value(state: &State, addr: (u16, u16)) -> Result<Value, ComputeError> {
+    let cell = state.get_at(addr);
+
+    if !cell.computed.is_computed {
+        panic!(
+            "REFERENCE IS NOT COMPUTED @ {} {:?}",
+            format_reference(addr),
+            addr
+        );
+    }
+
+    // pass the error on as-is so it keeps its origin
+    if let Some(err) = &cell.computed.error {
+        return Err(err.clone());
+    }
+
+    Ok(cell.computed.value.clone().unwrap())
+}
+
+/// An operator written in front of a value
+#[derive(Debug, PartialEq)]
 pub enum UnaryOp {
+    /// `-`
     Negative,
+    /// `+`, which leaves a number as it is
+    Positive,
 }
 
-#[derive(Debug, PartialEq)]
+/// An operator written between two values
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BinaryOp {
+    /// `+`
     Add,
+    /// `-`
     Subtract,
+    /// `*`
     Multiply,
+    /// `/`
     Divide,
+    /// `%`
+    Modulo,
+    /// `^`
+    Power,
+    /// `&`, joining two values as text
+    Concat,
+    /// `=`
+    Equal,
+    /// `<>`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `<=`
+    LessEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterEqual,
+}
+
+impl BinaryOp {
+    /// Whether the operator compares its values, giving TRUE or FALSE
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+        )
+    }
+}
+
+/// A rectangle of cells, such as `A1:B3`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Range {
+    /// Inclusive corners, with start <= end on both axes
+    pub start: (u16, u16),
+    /// The bottom right corner
+    pub end: (u16, u16),
+}
+
+impl Range {
+    /// Whether the cell is inside the range
+    pub fn contains(&self, (r, c): (u16, u16)) -> bool {
+        self.start.0 <= r && r <= self.end.0 && self.start.1 <= c && c <= self.end.1
+    }
+
+    /// Every cell of the range that exists in the state; anything beyond is blank
+    pub fn cells(&self, state: &State) -> Vec<(u16, u16)> {
+        state.cells_in((self.start, self.end))
+    }
+}
+
+/// A single cell reference. Either axis may be pinned with `$` (as in $A$1, A$1 or $A1)
+/// so that it stays put when the formula is copied to another cell.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CellRef {
+    /// The cell referred to
+    pub addr: (u16, u16),
+    /// Whether the row is pinned with `$`
+    pub absolute_row: bool,
+    /// Whether the column is pinned with `$`
+    pub absolute_column: bool,
+}
+
+impl CellRef {
+    /// Move the relative parts of the reference, or None if it would leave the grid
+    pub fn shift(&self, (rows, columns): (i32, i32)) -> Option<CellRef> {
+        Some(CellRef {
+            addr: (
+                shift_axis(self.addr.0, self.absolute_row, rows)?,
+                shift_axis(self.addr.1, self.absolute_column, columns)?,
+            ),
+            ..*self
+        })
+    }
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dollar = |absolute: bool| if absolute { "$" } else { "" };
+        write!(
+            f,
+            "{}{}{}{}",
+            dollar(self.absolute_column),
+            format_column(self.addr.1),
+            dollar(self.absolute_row),
+            self.addr.0 as u32 + 1
+        )
+    }
 }
 
-pub enum Function {
-    Sum,
-    Avg,
-    Pow,
+fn shift_axis(position: u16, absolute: bool, by: i32) -> Option<u16> {
+    if absolute {
+        return Some(position);
+    }
+
+    u16::try_from(position as i32 + by).ok()
 }
 
-pub fn resolve_reference(reference: &String) -> Result<(u16, u16), String> {
-    let re = Regex::new(r"^([A-Z]+)(\d+)$").unwrap();
+/// Format a column index as its letters, with 0 as A and 26 as AA
+pub fn format_column(column: u16) -> String {
+    let mut column = column as u32 + 1;
+    let mut letters = Vec::new();
+    while column > 0 {
+        let digit = (column - 1) % 26;
+        letters.push((b'A' + digit as u8) as char);
+        column = (column - 1) / 26;
+    }
+
+    letters.into_iter().rev().collect()
+}
 
-    let Some(captures) = re.captures(&reference) else {
-        return Err(format!("Could not parse reference: {}", reference));
+/// Format an address as an A1-style reference
+pub fn format_reference((row, column): (u16, u16)) -> String {
+    format!("{}{}", format_column(column), row as u32 + 1)
+}
+
+fn resolve_column(letters: &str) -> Option<u16> {
+    let mut column: u32 = 0;
+    for digit in letters.chars() {
+        column = column * 26 + (digit as u32 - 'A' as u32 + 1);
+        if column > u16::MAX as u32 {
+            return None;
+        }
+    }
+
+    Some(column as u16 - 1)
+}
+
+fn resolve_row(digits: &str) -> Option<u16> {
+    match digits.parse::<u16>() {
+        Ok(0) | Err(_) => None,
+        Ok(row) => Some(row - 1),
+    }
+}
+
+/// Parse an A1-style reference, where either axis may be pinned with `$`
+pub fn parse_reference(reference: &str) -> Result<CellRef, ComputeError> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"^(\$?)([A-Z]+)(\$?)(\d+)$").unwrap());
+
+    let Some(captures) = re.captures(reference) else {
+        return Err(format!("Could not parse reference: {}", reference).into());
     };
 
-    let mut column = 0;
-    for digit in captures[1].chars() {
-        column = column * 26 + ((digit as u8) - b'A' + 1);
+    let Some(column) = resolve_column(&captures[2]) else {
+        return Err(format!("Could not parse column for reference: {}", reference).into());
+    };
+
+    let Some(row) = resolve_row(&captures[4]) else {
+        return Err(format!("Could not parse row for reference: {}", reference).into());
+    };
+
+    Ok(CellRef {
+        addr: (row, column),
+        absolute_row: !captures[3].is_empty(),
+        absolute_column: !captures[1].is_empty(),
+    })
+}
+
+/// Resolve an A1-style reference (`B3`, `$B$3`) to a zero-based (row, column) address
+pub fn resolve_reference(reference: &str) -> Result<(u16, u16), ComputeError> {
+    parse_reference(reference).map(|reference| reference.addr)
+}
+
+/// Resolve A1:C10, B:B (whole column) or 3:3 (whole row)
+pub fn resolve_range(reference: &str) -> Result<Range, ComputeError> {
+    let err = || Err(format!("Could not parse range: {}", reference).into());
+
+    let Some((from, to)) = reference.split_once(':') else {
+        return err();
+    };
+
+    static CELLS: OnceLock<Regex> = OnceLock::new();
+    static COLUMNS: OnceLock<Regex> = OnceLock::new();
+    static ROWS: OnceLock<Regex> = OnceLock::new();
+    let cells = CELLS.get_or_init(|| Regex::new(r"^\$?[A-Z]+\$?\d+$").unwrap());
+    let columns = COLUMNS.get_or_init(|| Regex::new(r"^\$?[A-Z]+$").unwrap());
+    let rows = ROWS.get_or_init(|| Regex::new(r"^\$?\d+$").unwrap());
+
+    let (start, end) = if cells.is_match(from) && cells.is_match(to) {
+        (resolve_reference(from)?, resolve_reference(to)?)
+    } else if columns.is_match(from) && columns.is_match(to) {
+        let (from, to) = (from.trim_start_matches('$'), to.trim_start_matches('$'));
+        let (Some(from), Some(to)) = (resolve_column(from), resolve_column(to)) else {
+            return err();
+        };
+        ((0, from), (u16::MAX, to))
+    } else if rows.is_match(from) && rows.is_match(to) {
+        let (from, to) = (from.trim_start_matches('$'), to.trim_start_matches('$'));
+        let (Some(from), Some(to)) = (resolve_row(from), resolve_row(to)) else {
+            return err();
+        };
+        ((from, 0), (to, u16::MAX))
+    } else {
+        return err();
+    };
+
+    Ok(Range {
+        start: (start.0.min(end.0), start.1.min(end.1)),
+        end: (start.0.max(end.0), start.1.max(end.1)),
+    })
+}
+
+/// Move the relative parts of a reference or range (as written in a formula) by the
+/// given number of rows and columns, or None if any part would leave the grid
+pub fn shift_reference(reference: &str, offset: (i32, i32)) -> Option<String> {
+    if let Ok(cell) = parse_reference(reference) {
+        return Some(cell.shift(offset)?.to_string());
     }
 
-    let Ok(row) = (&captures[2]).parse::<u16>() as Result<u16, _> else {
-        // I think this should only happen on overflow?
-        return Err(format!(
-            "Could not parse column for reference: {}",
-            reference
-        ));
+    resolve_range(reference).ok()?;
+    let (from, to) = reference.split_once(':')?;
+    Some(format!(
+        "{}:{}",
+        shift_range_end(from, offset)?,
+        shift_range_end(to, offset)?
+    ))
+}
+
+// One end of a range, which may be a cell (A1), a whole column (B) or a whole row (3)
+fn shift_range_end(end: &str, (rows, columns): (i32, i32)) -> Option<String> {
+    if let Ok(cell) = parse_reference(end) {
+        return Some(cell.shift((rows, columns))?.to_string());
+    }
+
+    let absolute = end.starts_with('$');
+    let dollar = if absolute { "$" } else { "" };
+    let end = end.trim_start_matches('$');
+
+    match resolve_row(end) {
+        Some(row) => Some(format!(
+            "{}{}",
+            dollar,
+            shift_axis(row, absolute, rows)? as u32 + 1
+        )),
+        None => {
+            let column = shift_axis(resolve_column(end)?, absolute, columns)?;
+            Some(format!("{}{}", dollar, format_column(column)))
+        }
+    }
+}
+
+/// Whether a splice moves rows or columns
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Axis {
+    /// Rows, moving cells up or down
+    Row,
+    /// Columns, moving cells left or right
+    Column,
+}
+
+/// Rows (or columns) inserted before `at`, or deleted from `at` on, which moves
+/// everything after them along
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Splice {
+    /// Blank rows or columns put in
+    Insert {
+        /// Whether rows or columns are inserted
+        axis: Axis,
+        /// Where the first one goes
+        at: u16,
+        /// How many there are
+        count: u16,
+    },
+    /// Rows or columns taken out
+    Delete {
+        /// Whether rows or columns are deleted
+        axis: Axis,
+        /// The first one deleted
+        at: u16,
+        /// How many there are
+        count: u16,
+    },
+}
+
+impl Splice {
+    /// Whether the splice moves rows or columns
+    pub fn axis(&self) -> Axis {
+        match self {
+            Splice::Insert { axis, .. } | Splice::Delete { axis, .. } => *axis,
+        }
+    }
+
+    /// The splice that puts everything back where it was
+    pub fn inverse(&self) -> Splice {
+        match *self {
+            Splice::Insert { axis, at, count } => Splice::Delete { axis, at, count },
+            Splice::Delete { axis, at, count } => Splice::Insert { axis, at, count },
+        }
+    }
+
+    /// Where a row (or column) ends up, or None if it was deleted or pushed off the grid
+    pub fn index(&self, idx: u16) -> Option<u16> {
+        match *self {
+            Splice::Insert { at, count, .. } if idx >= at => idx.checked_add(count),
+            Splice::Delete { at, count, .. } if idx >= at => match idx - at < count {
+                true => None,
+                false => Some(idx - count),
+            },
+            _ => Some(idx),
+        }
+    }
+
+    /// Where the rows (or columns) from `start` to `end` end up, or None if they were all
+    /// deleted. Ends that were deleted move in to the nearest ones that are left.
+    pub fn span(&self, start: u16, end: u16) -> Option<(u16, u16)> {
+        match *self {
+            Splice::Insert { .. } => {
+                Some((self.index(start)?, self.index(end).unwrap_or(u16::MAX)))
+            }
+            Splice::Delete { at, .. } => {
+                let start = self.index(start).unwrap_or(at);
+                let end = match self.index(end) {
+                    Some(end) => end,
+                    None => at.checked_sub(1)?,
+                };
+                (start <= end).then_some((start, end))
+            }
+        }
+    }
+
+    /// Where a cell ends up, or None if it was deleted or pushed off the grid
+    pub fn address(&self, (row, column): (u16, u16)) -> Option<(u16, u16)> {
+        match self.axis() {
+            Axis::Row => Some((self.index(row)?, column)),
+            Axis::Column => Some((row, self.index(column)?)),
+        }
+    }
+}
+
+/// Move a reference or range (as written in a formula) to follow the cells it points at
+/// through a splice, pinned with `$` or not. None if the cells were deleted.
+pub fn splice_reference(reference: &str, splice: Splice) -> Option<String> {
+    if let Ok(cell) = parse_reference(reference) {
+        let addr = splice.address(cell.addr)?;
+        return Some(CellRef { addr, ..cell }.to_string());
+    }
+
+    resolve_range(reference).ok()?;
+    let (from, to) = reference.split_once(':')?;
+
+    // whole columns don't move when rows do, and the other way around
+    let axis = splice.axis();
+    let (Some(a), Some(b)) = (range_end_index(from, axis), range_end_index(to, axis)) else {
+        return Some(reference.to_string());
     };
 
-    Ok((row - 1, column as u16 - 1))
+    let (start, end) = splice.span(a.min(b), a.max(b))?;
+    let (a, b) = if a <= b { (start, end) } else { (end, start) };
+    Some(format!(
+        "{}:{}",
+        set_range_end_index(from, axis, a),
+        set_range_end_index(to, axis, b)
+    ))
+}
+
+// The row (or column) one end of a range is on, if it has one
+fn range_end_index(end: &str, axis: Axis) -> Option<u16> {
+    if let Ok(cell) = parse_reference(end) {
+        return Some(match axis {
+            Axis::Row => cell.addr.0,
+            Axis::Column => cell.addr.1,
+        });
+    }
+
+    let end = end.trim_start_matches('$');
+    match axis {
+        Axis::Row => resolve_row(end),
+        Axis::Column if resolve_row(end).is_none() => resolve_column(end),
+        Axis::Column => None,
+    }
+}
+
+fn set_range_end_index(end: &str, axis: Axis, idx: u16) -> String {
+    if let Ok(mut cell) = parse_reference(end) {
+        match axis {
+            Axis::Row => cell.addr.0 = idx,
+            Axis::Column => cell.addr.1 = idx,
+        }
+        return cell.to_string();
+    }
+
+    let dollar = if end.starts_with('$') { "$" } else { "" };
+    match axis {
+        Axis::Row => format!("{}{}", dollar, idx as u32 + 1),
+        Axis::Column => format!("{}{}", dollar, format_column(idx)),
+    }
 }
 
 #[cfg(test)]
@@ -129,10 +611,146 @@ mod tests {
     #[test]
     fn test_resolve_references() {
         assert_eq!(
-            resolve_reference(&"123".to_string()),
-            Err("Could not parse reference: 123".to_string())
+            resolve_reference("123"),
+            Err(ComputeError::from(
+                "Could not parse reference: 123".to_string()
+            ))
+        );
+        assert_eq!(resolve_reference("A1"), Ok((0, 0)));
+        assert_eq!(resolve_reference("AA11"), Ok((10, 26)));
+        assert_eq!(
+            resolve_reference("A0"),
+            Err(ComputeError::from(
+                "Could not parse row for reference: A0".to_string()
+            ))
+        );
+
+        assert_eq!(resolve_reference("$B$2"), Ok((1, 1)));
+        assert_eq!(
+            parse_reference("B$2"),
+            Ok(CellRef {
+                addr: (1, 1),
+                absolute_row: true,
+                absolute_column: false
+            })
+        );
+        assert_eq!(
+            parse_reference("$B2"),
+            Ok(CellRef {
+                addr: (1, 1),
+                absolute_row: false,
+                absolute_column: true
+            })
+        );
+        assert!(parse_reference("B2$").is_err());
+        assert!(parse_reference("$$B2").is_err());
+    }
+
+    #[test]
+    fn test_shift_reference() {
+        let shift = |reference: &str, offset| shift_reference(reference, offset);
+
+        assert_eq!(shift("A1", (2, 1)), Some("B3".to_string()));
+        assert_eq!(shift("$A$1", (2, 1)), Some("$A$1".to_string()));
+        assert_eq!(shift("A$1", (2, 1)), Some("B$1".to_string()));
+        assert_eq!(shift("$A1", (2, 1)), Some("$A3".to_string()));
+        assert_eq!(shift("B2", (-1, -1)), Some("A1".to_string()));
+        assert_eq!(shift("A1", (-1, 0)), None);
+        assert_eq!(shift("$A$1", (-1, -1)), Some("$A$1".to_string()));
+
+        assert_eq!(shift("A1:$B$3", (1, 1)), Some("B2:$B$3".to_string()));
+        assert_eq!(shift("B:$C", (5, 1)), Some("C:$C".to_string()));
+        assert_eq!(shift("$3:4", (1, 5)), Some("$3:5".to_string()));
+        assert_eq!(shift("A:A", (0, -1)), None);
+        assert_eq!(shift("SUM", (1, 1)), None);
+    }
+
+    #[test]
+    fn test_splice_reference() {
+        let insert_rows = Splice::Insert {
+            axis: Axis::Row,
+            at: 1,
+            count: 2,
+        };
+        let splice = |reference: &str| splice_reference(reference, insert_rows);
+
+        assert_eq!(splice("A1"), Some("A1".to_string()));
+        assert_eq!(splice("$B$2"), Some("$B$4".to_string()));
+        assert_eq!(splice("A1:B2"), Some("A1:B4".to_string()));
+        assert_eq!(splice("B2:A1"), Some("B4:A1".to_string()));
+        assert_eq!(splice("2:$3"), Some("4:$5".to_string()));
+        assert_eq!(splice("B:C"), Some("B:C".to_string()));
+
+        let delete_columns = Splice::Delete {
+            axis: Axis::Column,
+            at: 1,
+            count: 2,
+        };
+        let splice = |reference: &str| splice_reference(reference, delete_columns);
+
+        assert_eq!(splice("A1"), Some("A1".to_string()));
+        assert_eq!(splice("B1"), None);
+        assert_eq!(splice("$D1"), Some("$B1".to_string()));
+        // ranges shrink to the columns that are left, unless there are none
+        assert_eq!(splice("A1:D1"), Some("A1:B1".to_string()));
+        assert_eq!(splice("C1:E1"), Some("B1:C1".to_string()));
+        assert_eq!(splice("A1:B1"), Some("A1:A1".to_string()));
+        assert_eq!(splice("B:C"), None);
+        assert_eq!(splice("B:$E"), Some("B:$C".to_string()));
+        assert_eq!(splice("3:4"), Some("3:4".to_string()));
+    }
+
+    #[test]
+    fn test_format_reference() {
+        assert_eq!(format_reference((0, 0)), "A1");
+        assert_eq!(format_reference((10, 26)), "AA11");
+        assert_eq!(format_reference((99, 701)), "ZZ100");
+        assert_eq!(format_reference((0, 702)), "AAA1");
+
+        for column in [0, 25, 26, 701, 702, 18277] {
+            assert_eq!(resolve_column(&format_column(column)), Some(column));
+        }
+    }
+
+    #[test]
+    fn test_resolve_range() {
+        assert_eq!(
+            resolve_range("A1:C10"),
+            Ok(Range {
+                start: (0, 0),
+                end: (9, 2)
+            })
+        );
+        assert_eq!(
+            resolve_range("C10:A1"),
+            Ok(Range {
+                start: (0, 0),
+                end: (9, 2)
+            })
+        );
+        assert_eq!(
+            resolve_range("B:B"),
+            Ok(Range {
+                start: (0, 1),
+                end: (u16::MAX, 1)
+            })
+        );
+        assert_eq!(
+            resolve_range("3:4"),
+            Ok(Range {
+                start: (2, 0),
+                end: (3, u16::MAX)
+            })
+        );
+        assert_eq!(
+            resolve_range("A1:B"),
+            Err(ComputeError::from(
+                "Could not parse range: A1:B".to_string()
+            ))
+        );
+        assert_eq!(
+            resolve_range("A1"),
+            Err(ComputeError::from("Could not parse range: A1".to_string()))
         );
-        assert_eq!(resolve_reference(&"A1".to_string()), Ok((0, 0)));
-        assert_eq!(resolve_reference(&"AA11".to_string()), Ok((10, 26)));
     }
 }
@@ -1,38 +1,146 @@
+use crate::compute::error::{CellError, ComputeError};
+use crate::compute::function::Function;
 use crate::compute::node::*;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Parse the raw contents of a cell. Only a leading `=` makes a formula, and one that
+/// doesn't parse is an error. Anything else is a number if it reads as a finite one, or
+/// text otherwise; a leading `'` keeps number-like text as text.
+pub fn parse_cell(value: &str) -> Result<Node, ComputeError> {
+    if let Some(formula) = value.strip_prefix('=') {
+        return parse(formula);
+    }
+
+    if let Some(text) = value.strip_prefix('\'') {
+        return Ok(Node::Text(text.to_string()));
+    }
 
-// Parse a cell value (string) into a Node
-pub fn parse(cell: &str) -> Result<Node, String> {
+    match value.trim().parse::<f64>() {
+        Ok(num) if num.is_finite() => Ok(Node::Literal(num)),
+        _ => Ok(Node::Text(value.to_string())),
+    }
+}
+
+/// Whether the raw contents of a cell are read as a formula rather than a number or text
+pub fn is_formula(value: &str) -> bool {
+    value.starts_with('=')
+}
+
+/// Rewrite the raw contents of a cell for a copy placed `offset` rows and columns away.
+/// Relative references move with the copy while the parts pinned with `$` stay put, and
+/// references pushed off the grid become #REF!. Text is left as it is.
+pub fn shift_cell(value: &str, offset: (i32, i32)) -> String {
+    if !is_formula(value) {
+        return value.to_string();
+    }
+
+    rewrite_references(value, |reference| shift_reference(reference, offset))
+}
+
+/// Rewrite the raw contents of a cell for rows or columns inserted or deleted elsewhere in
+/// the sheet. References follow the cells they point at, pinned with `$` or not, and ones
+/// to deleted cells become #REF!.
+pub fn splice_cell(value: &str, splice: Splice) -> String {
+    if !is_formula(value) {
+        return value.to_string();
+    }
+
+    rewrite_references(value, |reference| splice_reference(reference, splice))
+}
+
+// Replace every reference and range in a formula with what `rewrite` makes of it, or
+// #REF! if it gives nothing back. String literals and function names are copied as-is.
+fn rewrite_references<F>(formula: &str, rewrite: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let is_term_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '$' | ':' | '.' | '_');
+
+    let mut result = String::new();
+    let mut rest = formula;
+    while let Some(char) = rest.chars().next() {
+        if char == '"' {
+            // an escaped "" just closes one literal and opens the next
+            let end = rest[1..].find('"').map_or(rest.len(), |idx| idx + 2);
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if is_term_char(char) {
+            let end = rest.find(|c| !is_term_char(c)).unwrap_or(rest.len());
+            let (term, after) = rest.split_at(end);
+
+            let is_function = after.trim_start().starts_with('(');
+            let is_reference = parse_reference(term).is_ok() || resolve_range(term).is_ok();
+            if is_reference && !is_function {
+                result.push_str(&rewrite(term).unwrap_or_else(|| "#REF!".to_string()));
+            } else {
+                result.push_str(term);
+            }
+
+            rest = after;
+        } else {
+            result.push(char);
+            rest = &rest[char.len_utf8()..];
+        }
+    }
+
+    result
+}
+
+/// Parse a formula (without its leading `=`) into a Node
+pub fn parse(cell: &str) -> Result<Node, ComputeError> {
     // STEP 1: SPLIT STRING INTO TERMS
     let terms = split_into_terms(cell);
 
     // STEP 2: REDUCE TERMS INTO TREE
     // order of operations
     // 2.1 Parens & Functions
-    // 2.2 Mult / Divide
-    // 2.2 Add / Sub
+    // 2.2 Operators, following PRECEDENCE
 
-    let paren_stack = match reduce_paren_stack(terms) {
-        Ok(x) => x,
-        Err(e) => return Err(e),
-    };
+    let paren_stack = reduce_paren_stack(terms)?;
 
     let terms = match paren_stack {
         ParenStack::Parens(terms) => terms,
         _ => panic!("Internal err"),
     };
 
-    match make_node(terms) {
-        Ok(node) => Ok(node),
-        Err(err) => Err(err),
-    }
+    make_node(terms)
 }
 
 fn split_into_terms(cell: &str) -> Vec<String> {
     let mut current_term = String::from("");
     let mut terms: Vec<String> = Vec::new();
-    for char in cell.chars() {
+    let mut chars = cell.chars().peekable();
+    while let Some(char) = chars.next() {
         match char {
-            '+' | '-' | '*' | '/' | '(' | ')' => {
+            // string literals are kept whole, quotes included; "" is an escaped quote
+            '"' => {
+                if !current_term.trim().is_empty() {
+                    terms.push(current_term.trim().to_string());
+                }
+                current_term = String::from("\"");
+
+                while let Some(char) = chars.next() {
+                    current_term.push(char);
+                    if char == '"' {
+                        match chars.next_if_eq(&'"') {
+                            Some(escaped) => current_term.push(escaped),
+                            None => break,
+                        }
+                    }
+                }
+
+                terms.push(current_term);
+                current_term = String::from("");
+            }
+            // two-character comparisons: <=, >=, <>
+            '=' | '>' if current_term.is_empty() && matches!(terms.last(), Some(t) if t == "<") => {
+                terms.last_mut().unwrap().push(char)
+            }
+            '=' if current_term.is_empty() && matches!(terms.last(), Some(t) if t == ">") => {
+                terms.last_mut().unwrap().push(char)
+            }
+            '+' | '-' | '*' | '/' | '^' | '%' | '&' | '(' | ')' | ',' | '=' | '<' | '>' => {
                 if !current_term.trim().is_empty() {
                     terms.push(current_term.trim().to_string());
                     current_term = String::from("");
@@ -54,17 +162,25 @@ fn split_into_terms(cell: &str) -> Vec<String> {
 enum ParenStack {
     Term(String),
     Parens(Vec<ParenStack>),
+    Function(String, Vec<ParenStack>),
+}
+
+fn is_function_name(term: &str) -> bool {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_.]*$").unwrap())
+        .is_match(term)
 }
 
 fn reduce_paren_stack(terms: Vec<String>) -> Result<ParenStack, String> {
     let mut root = ParenStack::Parens(Vec::new());
     let mut depth = 0;
 
-    fn push(root: &mut ParenStack, depth: i32, term: ParenStack) {
+    // the list of terms currently being filled at the given depth
+    fn target(root: &mut ParenStack, depth: i32) -> &mut Vec<ParenStack> {
         let mut target = root;
         for _ in 0..depth {
             match target {
-                ParenStack::Parens(ref mut vec) => {
+                ParenStack::Parens(ref mut vec) | ParenStack::Function(_, ref mut vec) => {
                     target = vec.last_mut().unwrap();
                 }
                 _ => panic!("Internal err!"),
@@ -72,7 +188,7 @@ fn reduce_paren_stack(terms: Vec<String>) -> Result<ParenStack, String> {
         }
 
         match target {
-            ParenStack::Parens(ref mut vec) => vec.push(term),
+            ParenStack::Parens(ref mut vec) | ParenStack::Function(_, ref mut vec) => vec,
             _ => panic!("Internal err!"),
         }
     }
@@ -80,7 +196,21 @@ fn reduce_paren_stack(terms: Vec<String>) -> Result<ParenStack, String> {
     for term in terms.into_iter() {
         match term.trim() {
             "(" => {
-                push(&mut root, depth, ParenStack::Parens(Vec::new()));
+                let vec = target(&mut root, depth);
+
+                // An identifier directly before a paren is a function call
+                let group = match vec.pop() {
+                    Some(ParenStack::Term(name)) if is_function_name(&name) => {
+                        ParenStack::Function(name, Vec::new())
+                    }
+                    Some(prev) => {
+                        vec.push(prev);
+                        ParenStack::Parens(Vec::new())
+                    }
+                    None => ParenStack::Parens(Vec::new()),
+                };
+
+                vec.push(group);
                 depth += 1;
             }
             ")" => {
@@ -89,7 +219,7 @@ fn reduce_paren_stack(terms: Vec<String>) -> Result<ParenStack, String> {
                 }
                 depth -= 1;
             }
-            _ => push(&mut root, depth, ParenStack::Term(term.to_string())),
+            _ => target(&mut root, depth).push(ParenStack::Term(term.to_string())),
         }
     }
     if depth > 0 {
@@ -99,89 +229,241 @@ fn reduce_paren_stack(terms: Vec<String>) -> Result<ParenStack, String> {
     Ok(root)
 }
 
-fn make_node(raw_terms: Vec<ParenStack>) -> Result<Node, String> {
-    enum Computed {
-        Computed(Node),
-        Raw(String),
+// Split the terms of a function call on top-level commas and build each argument
+fn make_args(raw_terms: Vec<ParenStack>) -> Result<Vec<Node>, ComputeError> {
+    if raw_terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut args = Vec::new();
+    let mut current = Vec::new();
+    for term in raw_terms.into_iter() {
+        match term {
+            ParenStack::Term(t) if t == "," => {
+                if current.is_empty() {
+                    return Err("Empty function argument".to_string().into());
+                }
+                args.push(make_node(current)?);
+                current = Vec::new();
+            }
+            _ => current.push(term),
+        }
+    }
+
+    if current.is_empty() {
+        return Err("Empty function argument".to_string().into());
     }
+    args.push(make_node(current)?);
+
+    Ok(args)
+}
+
+enum Computed {
+    Computed(Node),
+    Raw(String),
+}
+
+enum Associativity {
+    Left,
+    Right,
+}
+
+enum Level {
+    Binary(Associativity, &'static [(&'static str, BinaryOp)]),
+    Unary,
+}
+
+// Operator precedence, from tightest to loosest binding
+const PRECEDENCE: &[Level] = &[
+    Level::Binary(Associativity::Right, &[("^", BinaryOp::Power)]),
+    Level::Unary,
+    Level::Binary(
+        Associativity::Left,
+        &[
+            ("*", BinaryOp::Multiply),
+            ("/", BinaryOp::Divide),
+            ("%", BinaryOp::Modulo),
+        ],
+    ),
+    Level::Binary(
+        Associativity::Left,
+        &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+    ),
+    Level::Binary(Associativity::Left, &[("&", BinaryOp::Concat)]),
+    Level::Binary(
+        Associativity::Left,
+        &[
+            ("=", BinaryOp::Equal),
+            ("<>", BinaryOp::NotEqual),
+            ("<", BinaryOp::Less),
+            ("<=", BinaryOp::LessEqual),
+            (">", BinaryOp::Greater),
+            (">=", BinaryOp::GreaterEqual),
+        ],
+    ),
+];
+
+fn unary_op(op: &str) -> Option<UnaryOp> {
+    match op {
+        "-" => Some(UnaryOp::Negative),
+        "+" => Some(UnaryOp::Positive),
+        _ => None,
+    }
+}
+
+// Remove the operand at `index`, folding in any signs in front of it (`2 ^ -1`)
+fn take_operand(terms: &mut Vec<Computed>, index: usize) -> Result<Node, String> {
+    match terms.remove(index) {
+        Computed::Computed(node) => Ok(node),
+        Computed::Raw(op) => match unary_op(&op) {
+            Some(op) if index < terms.len() => {
+                Ok(Node::UnaryOp(op, Box::new(take_operand(terms, index)?)))
+            }
+            _ => Err("Bad operation before raw".to_string()),
+        },
+    }
+}
+
+fn reduce_binary(
+    terms: &mut Vec<Computed>,
+    associativity: &Associativity,
+    ops: &[(&str, BinaryOp)],
+) -> Result<(), String> {
+    let find_op = |term: &Computed| match term {
+        Computed::Raw(raw) => ops.iter().find(|(op, _)| op == raw).map(|(_, op)| *op),
+        _ => None,
+    };
+
+    // right-associative operators are folded starting from the end
+    let mut cursor: usize = match associativity {
+        Associativity::Left => 0,
+        Associativity::Right => terms.len().saturating_sub(1),
+    };
+
+    while cursor < terms.len() {
+        let Some(op) = find_op(&terms[cursor]) else {
+            match associativity {
+                Associativity::Left => cursor += 1,
+                Associativity::Right if cursor == 0 => break,
+                Associativity::Right => cursor -= 1,
+            }
+            continue;
+        };
 
+        if cursor < 1 || cursor >= terms.len() - 1 {
+            return Err("BinaryOp at boundary".to_string());
+        }
+
+        let next = take_operand(terms, cursor + 1)?;
+        let prev = match terms.remove(cursor - 1) {
+            Computed::Raw(_) => return Err("Bad operation after raw".to_string()),
+            Computed::Computed(node) => node,
+        };
+
+        terms[cursor - 1] = Computed::Computed(Node::BinaryOp(op, Box::new(prev), Box::new(next)));
+
+        if let Associativity::Right = associativity {
+            cursor -= 1;
+        }
+    }
+    Ok(())
+}
+
+// Reduce unary signs: an operator at the start or directly after another operator.
+// Scan right to left so that stacked signs (`- -5`) nest.
+fn reduce_unary(terms: &mut Vec<Computed>) -> Result<(), String> {
+    let mut cursor = terms.len();
+    while cursor > 0 {
+        cursor -= 1;
+
+        let op = match &terms[cursor] {
+            Computed::Raw(op) => match unary_op(op) {
+                Some(op) => op,
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        let is_prefix = cursor == 0 || matches!(terms[cursor - 1], Computed::Raw(_));
+        if !is_prefix || cursor + 1 >= terms.len() {
+            continue;
+        }
+
+        let Computed::Computed(next) = terms.remove(cursor + 1) else {
+            return Err("Bad unary op before raw".to_string());
+        };
+
+        terms[cursor] = Computed::Computed(Node::UnaryOp(op, Box::new(next)));
+    }
+    Ok(())
+}
+
+fn make_node(raw_terms: Vec<ParenStack>) -> Result<Node, ComputeError> {
     // Reduce parentheses, parse numbers, parse refs
     let mut terms: Vec<Computed> = Vec::new();
     for x in raw_terms.into_iter() {
         terms.push(match x {
-            ParenStack::Term(term) if resolve_reference(&term).is_ok() => {
-                Computed::Computed(Node::Reference(term))
+            ParenStack::Term(term) if parse_reference(&term).is_ok() => {
+                Computed::Computed(Node::Reference(parse_reference(&term).unwrap()))
+            }
+            ParenStack::Term(term) if term == CellError::Ref.code() => {
+                Computed::Computed(Node::InvalidReference)
+            }
+            ParenStack::Term(term) if resolve_range(&term).is_ok() => {
+                Computed::Computed(Node::Range(term))
+            }
+            ParenStack::Term(term) if term.starts_with('"') => {
+                match term.strip_suffix('"').filter(|_| term.len() > 1) {
+                    Some(text) => Computed::Computed(Node::Text(text[1..].replace("\"\"", "\""))),
+                    None => return Err("Unterminated string literal".to_string().into()),
+                }
+            }
+            ParenStack::Term(term) if term.eq_ignore_ascii_case("TRUE") => {
+                Computed::Computed(Node::Boolean(true))
+            }
+            ParenStack::Term(term) if term.eq_ignore_ascii_case("FALSE") => {
+                Computed::Computed(Node::Boolean(false))
             }
-            ParenStack::Term(term) => match term.parse::<f32>() {
+            ParenStack::Term(term) => match term.parse::<f64>() {
                 Ok(f) => Computed::Computed(Node::Literal(f)),
                 Err(_) => Computed::Raw(term),
             },
-            ParenStack::Parens(terms) => Computed::Computed(match make_node(terms) {
-                Ok(node) => node,
-                Err(e) => return Err(e),
-            }),
-        })
-    }
-
-    fn reduce_binary<F>(terms: &mut Vec<Computed>, f: F) -> Result<(), String>
-    where
-        F: Fn(&str) -> Option<BinaryOp>,
-    {
-        let mut cursor: usize = 0;
-        while cursor < terms.len() {
-            let op = match &terms[cursor] {
-                Computed::Raw(op) if f(op).is_some() => op.to_string(),
-                _ => {
-                    cursor += 1;
-                    continue;
+            ParenStack::Parens(terms) => Computed::Computed(make_node(terms)?),
+            ParenStack::Function(name, terms) => {
+                let Some(function) = Function::from_name(&name) else {
+                    return Err(ComputeError::new(
+                        CellError::Name,
+                        format!("Unknown function: {}", name),
+                    ));
+                };
+
+                let args = make_args(terms)?;
+                function.check_arity(args.len())?;
+
+                if !function.is_aggregate() && args.iter().any(|a| matches!(a, Node::Range(_))) {
+                    return Err(ComputeError::new(
+                        CellError::Value,
+                        format!("{} does not accept ranges", function.name()),
+                    ));
                 }
-            };
 
-            if cursor < 1 || cursor >= terms.len() - 1 {
-                return Err("BinaryOp at boundary".to_string());
+                Computed::Computed(Node::Function(function, args))
             }
-
-            let prev = match terms.remove(cursor - 1) {
-                Computed::Raw(_) => return Err("Bad multiplication after raw".to_string()),
-                Computed::Computed(node) => node,
-            };
-            let next = match terms.remove(cursor) {
-                Computed::Raw(_) => return Err("Bad multiplication before raw".to_string()),
-                Computed::Computed(node) => node,
-            };
-
-            terms[cursor - 1] = Computed::Computed(Node::BinaryOp(
-                f(op.as_str()).unwrap(),
-                Box::new(prev),
-                Box::new(next),
-            ));
-        }
-        Ok(())
-    }
-
-    // Reduce multiplication and division
-    if let Err(e) = reduce_binary(&mut terms, |op| match op {
-        "*" => Some(BinaryOp::Multiply),
-        "/" => Some(BinaryOp::Divide),
-        _ => None,
-    }) {
-        return Err(e);
+        })
     }
 
-    // Reduce addition and subtraction
-    if let Err(e) = reduce_binary(&mut terms, |op| match op {
-        "+" => Some(BinaryOp::Add),
-        "-" => Some(BinaryOp::Subtract),
-        _ => None,
-    }) {
-        return Err(e);
+    for level in PRECEDENCE {
+        match level {
+            Level::Unary => reduce_unary(&mut terms)?,
+            Level::Binary(associativity, ops) => reduce_binary(&mut terms, associativity, ops)?,
+        }
     }
 
-    if (&terms).len() != 1 {
-        Err("Could not fully reduce".to_string())
+    if terms.len() != 1 {
+        Err("Could not fully reduce".to_string().into())
     } else {
         match terms.into_iter().next().unwrap() {
-            Computed::Raw(_) => Err("Could not reduce from raw".to_string()),
+            Computed::Raw(_) => Err("Could not reduce from raw".to_string().into()),
             Computed::Computed(node) => Ok(node),
         }
     }
@@ -192,15 +474,9 @@ mod tests {
     use super::*;
 
     macro_rules! paren {
-        ( $( $x:expr ),+ ) => {{
-            let mut v = Vec::new();
-
-            $(
-                v.push(term!($x));
-            )*
-
-            ParenStack::Parens(v)
-        }};
+        ( $( $x:expr ),+ ) => {
+            ParenStack::Parens(vec![$( term!($x) ),+])
+        };
     }
 
     macro_rules! term {
@@ -223,6 +499,19 @@ mod tests {
             split_into_terms("( 1.5 * (1.2 - B3 ) + A4)"),
             vec!["(", "1.5", "*", "(", "1.2", "-", "B3", ")", "+", "A4", ")"]
         );
+        assert_eq!(
+            split_into_terms("A1<=2<>B1>=3=C1<4>5"),
+            vec!["A1", "<=", "2", "<>", "B1", ">=", "3", "=", "C1", "<", "4", ">", "5"]
+        );
+        assert_eq!(
+            split_into_terms("\"a, (b)\"&A1 & \"say \"\"hi\"\"\""),
+            vec!["\"a, (b)\"", "&", "A1", "&", "\"say \"\"hi\"\"\""]
+        );
+        assert_eq!(split_into_terms("\"open"), vec!["\"open"]);
+        assert_eq!(
+            split_into_terms("SUM(A1, 2)"),
+            vec!["SUM", "(", "A1", ",", "2", ")"]
+        );
     }
 
     #[test]
@@ -260,6 +549,18 @@ mod tests {
             reduce_paren_stack(vec!["3", "*", "(", "1", "+", "2", ")", ")"]),
             Err("Unmatched closing paren".to_string())
         );
+
+        assert_eq!(
+            reduce_paren_stack(vec!["2", "*", "SUM", "(", "A1", ",", "(", "1", ")", ")"]),
+            Ok(paren!(
+                "2",
+                "*",
+                ParenStack::Function(
+                    "SUM".to_string(),
+                    vec![term!("A1"), term!(","), paren!("1")]
+                )
+            ))
+        );
     }
 
     #[test]
@@ -267,6 +568,7 @@ mod tests {
         let easy_make_node = |ast: ParenStack| match ast {
             ParenStack::Term(t) => make_node(vec![ParenStack::Term(t)]),
             ParenStack::Parens(terms) => make_node(terms),
+            ParenStack::Function(..) => make_node(vec![ast]),
         };
 
         assert_eq!(easy_make_node(paren!("1")), Ok(Node::Literal(1.0)));
@@ -286,7 +588,7 @@ mod tests {
                 Box::new(Node::Literal(3.0)),
                 Box::new(Node::BinaryOp(
                     BinaryOp::Multiply,
-                    Box::new(Node::Reference("A2".to_string())),
+                    Box::new(Node::Reference(parse_reference("A2").unwrap())),
                     Box::new(Node::Literal(5.0)),
                 )),
             ))
@@ -314,8 +616,318 @@ mod tests {
                     Box::new(Node::Literal(3.0)),
                     Box::new(Node::Literal(4.0)),
                 )),
-                Box::new(Node::Reference("CC100".to_string())),
+                Box::new(Node::Reference(parse_reference("CC100").unwrap())),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_function() {
+        assert_eq!(
+            parse("SUM(A1, 2 * 3)"),
+            Ok(Node::Function(
+                Function::Sum,
+                vec![
+                    Node::Reference(parse_reference("A1").unwrap()),
+                    Node::BinaryOp(
+                        BinaryOp::Multiply,
+                        Box::new(Node::Literal(2.0)),
+                        Box::new(Node::Literal(3.0)),
+                    ),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            parse("1 + pow(2, max(1, 3))"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Add,
+                Box::new(Node::Literal(1.0)),
+                Box::new(Node::Function(
+                    Function::Pow,
+                    vec![
+                        Node::Literal(2.0),
+                        Node::Function(Function::Max, vec![Node::Literal(1.0), Node::Literal(3.0)]),
+                    ],
+                )),
             ))
         );
+
+        assert_eq!(
+            parse("NOPE(1)"),
+            Err(ComputeError::new(CellError::Name, "Unknown function: NOPE"))
+        );
+        assert_eq!(
+            parse("POW(1)"),
+            Err(ComputeError::new(
+                CellError::Value,
+                "POW expects at least 2 argument(s), got 1"
+            ))
+        );
+        assert_eq!(
+            parse("SUM(1, , 2)"),
+            Err(ComputeError::from("Empty function argument".to_string()))
+        );
+        assert_eq!(
+            parse("SUM(A1:B3, 3:3)"),
+            Ok(Node::Function(
+                Function::Sum,
+                vec![
+                    Node::Range("A1:B3".to_string()),
+                    Node::Range("3:3".to_string())
+                ],
+            ))
+        );
+        assert_eq!(
+            parse("ABS(A:A)"),
+            Err(ComputeError::new(
+                CellError::Value,
+                "ABS does not accept ranges"
+            ))
+        );
+        assert_eq!(
+            parse("1, 2"),
+            Err(ComputeError::from("Could not fully reduce".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_unary() {
+        let neg = |node: Node| Node::UnaryOp(UnaryOp::Negative, Box::new(node));
+
+        assert_eq!(parse("-5"), Ok(neg(Node::Literal(5.0))));
+        assert_eq!(
+            parse("+A1"),
+            Ok(Node::UnaryOp(
+                UnaryOp::Positive,
+                Box::new(Node::Reference(parse_reference("A1").unwrap()))
+            ))
+        );
+        assert_eq!(parse("- -5"), Ok(neg(neg(Node::Literal(5.0)))));
+        assert_eq!(
+            parse("3 * -A1"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Multiply,
+                Box::new(Node::Literal(3.0)),
+                Box::new(neg(Node::Reference(parse_reference("A1").unwrap()))),
+            ))
+        );
+        assert_eq!(
+            parse("-2 * 3"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Multiply,
+                Box::new(neg(Node::Literal(2.0))),
+                Box::new(Node::Literal(3.0)),
+            ))
+        );
+        assert_eq!(
+            parse("1 - -(A1 + 2)"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Subtract,
+                Box::new(Node::Literal(1.0)),
+                Box::new(neg(Node::BinaryOp(
+                    BinaryOp::Add,
+                    Box::new(Node::Reference(parse_reference("A1").unwrap())),
+                    Box::new(Node::Literal(2.0)),
+                ))),
+            ))
+        );
+        assert_eq!(
+            parse("SUM(-1, 2)"),
+            Ok(Node::Function(
+                Function::Sum,
+                vec![neg(Node::Literal(1.0)), Node::Literal(2.0)],
+            ))
+        );
+        assert_eq!(
+            parse("5 -"),
+            Err(ComputeError::from("BinaryOp at boundary".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let lit = |x: f64| Box::new(Node::Literal(x));
+        let op = |op: BinaryOp, l: Box<Node>, r: Box<Node>| Box::new(Node::BinaryOp(op, l, r));
+
+        // ^ is right-associative and binds tighter than *
+        assert_eq!(
+            parse("2 * 3 ^ 2 ^ 4"),
+            Ok(*op(
+                BinaryOp::Multiply,
+                lit(2.0),
+                op(
+                    BinaryOp::Power,
+                    lit(3.0),
+                    op(BinaryOp::Power, lit(2.0), lit(4.0))
+                ),
+            ))
+        );
+
+        // the other operators fold left to right
+        assert_eq!(
+            parse("7 % 4 * 2 - 1 - 1"),
+            Ok(*op(
+                BinaryOp::Subtract,
+                op(
+                    BinaryOp::Subtract,
+                    op(
+                        BinaryOp::Multiply,
+                        op(BinaryOp::Modulo, lit(7.0), lit(4.0)),
+                        lit(2.0)
+                    ),
+                    lit(1.0),
+                ),
+                lit(1.0),
+            ))
+        );
+
+        // unary minus binds looser than ^ but still applies to an exponent
+        assert_eq!(
+            parse("-2 ^ -1"),
+            Ok(Node::UnaryOp(
+                UnaryOp::Negative,
+                op(
+                    BinaryOp::Power,
+                    lit(2.0),
+                    Box::new(Node::UnaryOp(UnaryOp::Negative, lit(1.0))),
+                ),
+            ))
+        );
+
+        assert_eq!(
+            parse("2 ^"),
+            Err(ComputeError::from("BinaryOp at boundary".to_string()))
+        );
+        assert_eq!(
+            parse("2 * * 3"),
+            Err(ComputeError::from("Bad operation before raw".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_comparison() {
+        // comparisons bind looser than arithmetic
+        assert_eq!(
+            parse("A1 + 1 >= 2"),
+            Ok(Node::BinaryOp(
+                BinaryOp::GreaterEqual,
+                Box::new(Node::BinaryOp(
+                    BinaryOp::Add,
+                    Box::new(Node::Reference(parse_reference("A1").unwrap())),
+                    Box::new(Node::Literal(1.0)),
+                )),
+                Box::new(Node::Literal(2.0)),
+            ))
+        );
+        assert_eq!(
+            parse("TRUE <> false"),
+            Ok(Node::BinaryOp(
+                BinaryOp::NotEqual,
+                Box::new(Node::Boolean(true)),
+                Box::new(Node::Boolean(false)),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_text() {
+        assert_eq!(
+            parse("\"say \"\"hi\"\"\" & A1"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Concat,
+                Box::new(Node::Text("say \"hi\"".to_string())),
+                Box::new(Node::Reference(parse_reference("A1").unwrap())),
+            ))
+        );
+        assert_eq!(
+            parse("\"open"),
+            Err(ComputeError::from(
+                "Unterminated string literal".to_string()
+            ))
+        );
+        assert_eq!(parse("\"\""), Ok(Node::Text("".to_string())));
+    }
+
+    #[test]
+    fn test_parse_cell() {
+        assert_eq!(parse_cell("12"), Ok(Node::Literal(12.0)));
+        assert_eq!(parse_cell(" -1.5e3 "), Ok(Node::Literal(-1500.0)));
+        assert_eq!(parse_cell("Revenue"), Ok(Node::Text("Revenue".to_string())));
+        assert_eq!(parse_cell("'12"), Ok(Node::Text("12".to_string())));
+
+        // only a leading `=` makes a formula, however much the text looks like one
+        for text in ["Q1", "2024-01-15", "10:30", "nan", "inf", "SUM(A1", "1 + 2"] {
+            assert_eq!(parse_cell(text), Ok(Node::Text(text.to_string())));
+        }
+        assert_eq!(
+            parse_cell("=Revenue"),
+            Err(ComputeError::from("Could not reduce from raw".to_string()))
+        );
+        assert_eq!(
+            parse_cell("=1 + 2"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Add,
+                Box::new(Node::Literal(1.0)),
+                Box::new(Node::Literal(2.0)),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_absolute_reference() {
+        assert_eq!(
+            parse("$A$1 + A$2"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Add,
+                Box::new(Node::Reference(parse_reference("$A$1").unwrap())),
+                Box::new(Node::Reference(parse_reference("A$2").unwrap())),
+            ))
+        );
+        assert_eq!(
+            parse("SUM($A$1:B2)"),
+            Ok(Node::Function(
+                Function::Sum,
+                vec![Node::Range("$A$1:B2".to_string())]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_shift_cell() {
+        assert_eq!(shift_cell("=A1 * $B$1", (1, 0)), "=A2 * $B$1");
+        assert_eq!(shift_cell("=SUM(A1:A3) + $C1", (0, 2)), "=SUM(C1:C3) + $C1");
+        assert_eq!(
+            shift_cell("=A$1 & \"A1\"\"B2\"", (3, 1)),
+            "=B$1 & \"A1\"\"B2\""
+        );
+        assert_eq!(shift_cell("=LOG10 (B2)", (1, 1)), "=LOG10 (C3)");
+        assert_eq!(shift_cell("=B2 - A1", (-1, 0)), "=B1 - #REF!");
+        assert_eq!(
+            parse("B1 - #REF!"),
+            Ok(Node::BinaryOp(
+                BinaryOp::Subtract,
+                Box::new(Node::Reference(parse_reference("B1").unwrap())),
+                Box::new(Node::InvalidReference),
+            ))
+        );
+
+        // text is never rewritten
+        assert_eq!(shift_cell("Sales Q1", (1, 1)), "Sales Q1");
+        assert_eq!(shift_cell("Q1", (1, 1)), "Q1");
+        assert_eq!(shift_cell("'A1", (1, 1)), "'A1");
+        assert_eq!(shift_cell("12.5", (1, 1)), "12.5");
+    }
+
+    #[test]
+    fn test_splice_cell() {
+        let delete = Splice::Delete {
+            axis: Axis::Row,
+            at: 1,
+            count: 1,
+        };
+        assert_eq!(splice_cell("=A1 + $A$3 - A2", delete), "=A1 + $A$2 - #REF!");
+        assert_eq!(splice_cell("=SUM(A1:A3)", delete), "=SUM(A1:A2)");
+        assert_eq!(splice_cell("A3", delete), "A3");
     }
 }
@@ -0,0 +1,171 @@
+use crate::compute::error::{CellError, ComputeError};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The result of computing a cell
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    /// A finite number
+    Number(f64),
+    /// TRUE or FALSE
+    Bool(bool),
+    /// Text, from a cell that isn't a number or from a formula
+    Text(String),
+}
+
+impl Value {
+    /// Coerce to a number for arithmetic, with TRUE as 1 and FALSE as 0.
+    /// Text is only accepted if it reads as a number.
+    pub fn as_number(&self) -> Result<f64, ComputeError> {
+        match self {
+            Value::Number(num) => Ok(*num),
+            Value::Bool(true) => Ok(1.0),
+            Value::Bool(false) => Ok(0.0),
+            Value::Text(text) => text.trim().parse::<f64>().map_err(|_| {
+                ComputeError::new(
+                    CellError::Value,
+                    format!("Expected a number, got \"{}\"", text),
+                )
+            }),
+        }
+    }
+
+    /// Coerce to a boolean for logic, with any non-zero number as TRUE
+    pub fn as_bool(&self) -> Result<bool, ComputeError> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            Value::Number(num) => Ok(*num != 0.0),
+            Value::Text(text) if text.eq_ignore_ascii_case("TRUE") => Ok(true),
+            Value::Text(text) if text.eq_ignore_ascii_case("FALSE") => Ok(false),
+            Value::Text(text) => Err(ComputeError::new(
+                CellError::Value,
+                format!("Expected TRUE or FALSE, got \"{}\"", text),
+            )),
+        }
+    }
+
+    /// Text sorts after every other value and compares case-insensitively
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Text(x), Value::Text(y)) => Some(x.to_lowercase().cmp(&y.to_lowercase())),
+            (Value::Text(_), _) => Some(Ordering::Greater),
+            (_, Value::Text(_)) => Some(Ordering::Less),
+            (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+            (x, y) => x.as_number().ok()?.partial_cmp(&y.as_number().ok()?),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(num) => write!(f, "{}", format_number(*num)),
+            Value::Bool(true) => write!(f, "TRUE"),
+            Value::Bool(false) => write!(f, "FALSE"),
+            Value::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+// Significant digits an f64 reliably holds; anything past this is float noise (0.1 + 0.2)
+const PRECISION: i32 = 15;
+
+fn trim_zeros(num: String) -> String {
+    match num.split_once('e') {
+        Some((mantissa, exponent)) => format!("{}e{}", trim_zeros(mantissa.to_string()), exponent),
+        None if num.contains('.') => num.trim_end_matches('0').trim_end_matches('.').to_string(),
+        None => num,
+    }
+}
+
+/// Format a number rounded to the precision that is actually meaningful
+pub fn format_number(num: f64) -> String {
+    if num == 0.0 {
+        return "0".to_string();
+    }
+
+    let magnitude = num.abs().log10().floor() as i32;
+    if !(-9..PRECISION).contains(&magnitude) {
+        return trim_zeros(format!("{:.*e}", (PRECISION - 1) as usize, num));
+    }
+
+    let decimals = (PRECISION - magnitude - 1).max(0) as usize;
+    let formatted = trim_zeros(format!("{:.*}", decimals, num));
+
+    // rounding can leave a negative zero behind
+    if formatted == "-0" {
+        "0".to_string()
+    } else {
+        formatted
+    }
+}
+
+/// Round away digits beyond the meaningful precision
+pub fn round_to_precision(num: f64) -> f64 {
+    format_number(num).parse().unwrap_or(num)
+}
+
+/// Format a number into at most `width` characters, dropping decimals first and
+/// falling back to scientific notation. If nothing fits the cell is filled with #.
+pub fn fit_number(num: f64, width: usize) -> String {
+    let formatted = format_number(num);
+    if formatted.len() <= width {
+        return formatted;
+    }
+
+    if !formatted.contains('e') {
+        let integer_len = formatted.split('.').next().unwrap().len();
+        if integer_len < width {
+            let fitted = trim_zeros(format!("{:.*}", width - integer_len - 1, num));
+            if fitted.len() <= width && fitted != "-0" {
+                return fitted;
+            }
+        } else if integer_len == width {
+            // rounding up can carry into another digit
+            let fitted = format!("{:.0}", num);
+            if fitted.len() <= width {
+                return fitted;
+            }
+        }
+    }
+
+    for decimals in (0..PRECISION as usize).rev() {
+        let fitted = trim_zeros(format!("{:.*e}", decimals, num));
+        if fitted.len() <= width {
+            return fitted;
+        }
+    }
+
+    "#".repeat(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(20.0), "20");
+        assert_eq!(format_number(-1.5), "-1.5");
+        assert_eq!(format_number(16_777_217.01), "16777217.01");
+        assert_eq!(format_number(1.0 / 3.0), "0.333333333333333");
+        assert_eq!(format_number(1e21), "1e21");
+        assert_eq!(format_number(1.5e-12), "1.5e-12");
+        assert_eq!(format_number(-1e-20 + 1e-20), "0");
+    }
+
+    #[test]
+    fn test_fit_number() {
+        assert_eq!(fit_number(12.5, 7), "12.5");
+        assert_eq!(fit_number(1.0 / 3.0, 7), "0.33333");
+        assert_eq!(fit_number(-2.0 / 3.0, 7), "-0.6667");
+        assert_eq!(fit_number(1234567.8, 7), "1234568");
+        assert_eq!(fit_number(9999999.7, 7), "1e7");
+        assert_eq!(fit_number(-999999.7, 7), "-1e6");
+        assert_eq!(fit_number(999.7, 3), "1e3");
+        assert_eq!(fit_number(123456789.0, 7), "1.235e8");
+        assert_eq!(fit_number(-0.0000001, 7), "-1e-7");
+        assert_eq!(fit_number(-1.5e-123, 4), "####");
+    }
+}
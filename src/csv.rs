@@ -0,0 +1,257 @@
+use crate::file::FileError;
+use crate::history::History;
+use crate::state::{Address, DisplayCell, State};
+
+/// What to write for each cell when exporting
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Export {
+    /// The contents as typed, with formulas marked by a leading `=`
+    Raw,
+    /// The computed results, as shown in the grid
+    Computed,
+}
+
+/// Split delimited text into records (RFC 4180). Fields may be quoted, in which case they
+/// can hold delimiters, line breaks and quotes (written twice).
+pub fn parse(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, FileError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '"' if field.is_empty() => {
+                let start = line;
+                loop {
+                    match chars.next() {
+                        Some('"') => match chars.next_if_eq(&'"') {
+                            Some(escaped) => field.push(escaped),
+                            None => break,
+                        },
+                        Some(char) => {
+                            if char == '\n' {
+                                line += 1;
+                            }
+                            field.push(char)
+                        }
+                        None => return Err(FileError::at_line(start, "Unterminated quoted field")),
+                    }
+                }
+
+                match chars.peek() {
+                    None | Some('\r' | '\n') => {}
+                    Some(next) if *next == delimiter => {}
+                    _ => return Err(FileError::at_line(line, "Text after a quoted field")),
+                }
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            _ if char == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(char),
+        }
+    }
+
+    // the last line may or may not end with a line break
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Put records into the sheet from A1, replacing whatever it held. Fields are read the
+/// way typed cells are: numbers, formulas starting with `=`, and text, with a leading `'`
+/// marking text just as it does when typed.
+pub fn import(state: &mut State, records: Vec<Vec<String>>) {
+    let filled: Vec<Address> = state
+        .cells()
+        .filter(|(_, cell)| !cell.value.is_empty())
+        .map(|(addr, _)| addr)
+        .collect();
+    for addr in filled {
+        state.set_at(addr, DisplayCell::blank());
+    }
+
+    for (r, record) in records.into_iter().enumerate() {
+        for (c, field) in record.into_iter().enumerate() {
+            if field.is_empty() || r > u16::MAX as usize || c > u16::MAX as usize {
+                continue;
+            }
+
+            let value = if field.starts_with('=') || is_number(&field) {
+                field.trim().to_string()
+            } else {
+                field
+            };
+            state.set_at((r as u16, c as u16), DisplayCell::new(value));
+        }
+    }
+}
+
+/// A new sheet holding the contents of delimited text
+pub fn read(text: &str, delimiter: char) -> Result<State, FileError> {
+    let mut state = State::blank();
+    import(&mut state, parse(text, delimiter)?);
+    state.history = History::new();
+    Ok(state)
+}
+
+/// Write the sheet as delimited text, with every record as wide as the widest one. The
+/// sheet should be baked first when exporting computed results.
+pub fn write(state: &State, delimiter: char, export: Export) -> String {
+    write_area(state, ((0, 0), (u16::MAX, u16::MAX)), delimiter, export)
+}
+
+/// Write the cells between two corners (inclusive), leaving out empty rows and columns
+/// at the end
+pub fn write_area(
+    state: &State,
+    (start, end): (Address, Address),
+    delimiter: char,
+    export: Export,
+) -> String {
+    let mut fields: Vec<Vec<String>> = Vec::new();
+    for addr in state.cells_in((start, end)) {
+        let cell = state.get_at(addr);
+        let (r, c) = ((addr.0 - start.0) as usize, (addr.1 - start.1) as usize);
+        if r >= fields.len() {
+            fields.resize(r + 1, Vec::new());
+        }
+        if c >= fields[r].len() {
+            fields[r].resize(c + 1, String::new());
+        }
+
+        fields[r][c] = match export {
+            Export::Raw => raw_value(&cell.value),
+            Export::Computed => cell.computed.display.clone(),
+        };
+    }
+
+    let last_filled = |row: &Vec<String>| row.iter().rposition(|field| !field.is_empty());
+    let height = fields.iter().rposition(|row| last_filled(row).is_some());
+    let width = fields.iter().filter_map(last_filled).max();
+    let (Some(height), Some(width)) = (height, width) else {
+        return String::new();
+    };
+
+    let mut text = String::new();
+    for row in &fields[..=height] {
+        let record: Vec<String> = (0..=width)
+            .map(|c| quote(row.get(c).map_or("", |field| field), delimiter))
+            .collect();
+        text += &record.join(&delimiter.to_string());
+        text += "\r\n";
+    }
+
+    text
+}
+
+// Cell contents as typed, less the `'` that marks text unless the text would read back
+// as something else without it
+fn raw_value(value: &str) -> String {
+    match value.strip_prefix('\'') {
+        Some(text) if !text.starts_with(['=', '\'']) && !is_number(text) => text.to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn is_number(field: &str) -> bool {
+    field.trim().parse::<f64>().is_ok_and(|num| num.is_finite())
+}
+
+fn quote(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::bake;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("a,b,c\r\n1,,3\n", ',').map_err(|err| err.to_string()),
+            Ok(vec![
+                vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                vec!["1".to_string(), "".to_string(), "3".to_string()],
+            ])
+        );
+        assert_eq!(
+            parse("\"x, \"\"y\"\"\",\"two\nlines\"", ',').map_err(|err| err.to_string()),
+            Ok(vec![vec!["x, \"y\"".to_string(), "two\nlines".to_string()]])
+        );
+        assert_eq!(
+            parse("a\tb,c", '\t').map_err(|err| err.to_string()),
+            Ok(vec![vec!["a".to_string(), "b,c".to_string()]])
+        );
+        assert_eq!(
+            parse("a\n\"b", ',').map_err(|err| err.to_string()),
+            Err("Line 2: Unterminated quoted field".to_string())
+        );
+        assert_eq!(
+            parse("\"a\"b", ',').map_err(|err| err.to_string()),
+            Err("Line 1: Text after a quoted field".to_string())
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let mut state = read("Name,Q1,Total\nA1, 2.5 ,=B2*2\n", ',').unwrap();
+        assert_eq!(state.get_at((0, 1)).value, "Q1");
+        assert_eq!(state.get_at((1, 0)).value, "A1");
+        assert_eq!(state.get_at((1, 1)).value, "2.5");
+        assert_eq!(state.get_at((1, 2)).value, "=B2*2");
+        assert!(!state.history.is_modified());
+
+        bake(&mut state);
+        assert_eq!(state.get_at((1, 2)).computed.display, "5");
+    }
+
+    #[test]
+    fn test_write() {
+        let mut state = State::blank();
+        state.set_at((0, 0), DisplayCell::new("'12".to_string()));
+        state.set_at((0, 2), DisplayCell::new("=A2 * 2".to_string()));
+        state.set_at((1, 0), DisplayCell::new("2.5".to_string()));
+        state.set_at((0, 3), DisplayCell::new("'Note".to_string()));
+        state.set_at((1, 1), DisplayCell::new("Hello, \"world\"".to_string()));
+        state.set_at((1, 3), DisplayCell::new("''quoted".to_string()));
+        state.set_at((3, 0), DisplayCell::blank());
+        bake(&mut state);
+
+        // text that would read back as a number, a formula or marked text keeps its `'`
+        assert_eq!(
+            write(&state, ',', Export::Raw),
+            "'12,,=A2 * 2,Note\r\n2.5,\"Hello, \"\"world\"\"\",,''quoted\r\n"
+        );
+        assert_eq!(
+            write(&state, '\t', Export::Computed),
+            "12\t\t5\tNote\r\n2.5\t\"Hello, \"\"world\"\"\"\t\t'quoted\r\n"
+        );
+        assert_eq!(write(&State::blank(), ',', Export::Raw), "");
+
+        // raw exports read back to the same sheet
+        let mut read = read(&write(&state, ',', Export::Raw), ',').unwrap();
+        bake(&mut read);
+        for addr in [(0, 0), (0, 2), (0, 3), (1, 0), (1, 1), (1, 3)] {
+            assert_eq!(
+                read.get_at(addr).computed.value,
+                state.get_at(addr).computed.value
+            );
+        }
+        assert_eq!(read.get_at((0, 0)).value, "'12");
+        assert_eq!(read.get_at((0, 2)).value, "=A2 * 2");
+    }
+}
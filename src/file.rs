@@ -0,0 +1,515 @@
+use crate::compute::{
+    format_column, format_reference, resolve_range, resolve_reference, Iteration,
+};
+use crate::csv::{self, Export};
+use crate::history::History;
+use crate::state::{Alignment, DisplayCell, State, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The native format is plain text: a header line, then one line per non-blank cell
+// holding its reference and any attributes, a tab, and its escaped raw value. Columns
+// and rows that aren't the default size get a line of their own, such as `B:B width=12`,
+// with no value. Settings for the whole sheet, such as iterative calculation, are
+// attributes on the header line.
+//
+//   cell 1 iterations=100 tolerance=0.001
+//   A1	=4 * ( 2 + 3 )
+//   B2 align=right	Total
+//
+// Attributes are `key=value` pairs, and ones that aren't known are skipped so that
+// files written by later versions can still be opened.
+const HEADER: &str = "cell";
+const VERSION: u32 = 1;
+
+/// An error reading or writing a sheet, either as a native file or as delimited text
+#[derive(Debug)]
+pub enum FileError {
+    /// The file couldn't be read or written
+    Io(io::Error),
+    /// The text isn't in the format it was read as
+    Format {
+        /// The line it went wrong on, if it's known
+        line: Option<usize>,
+        /// What was wrong with it
+        message: String,
+    },
+}
+
+impl FileError {
+    pub(crate) fn at_line(line: usize, message: impl Into<String>) -> Self {
+        FileError::Format {
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+
+    fn format(message: impl Into<String>) -> Self {
+        FileError::Format {
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(err: io::Error) -> Self {
+        FileError::Io(err)
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(err) => write!(f, "{}", err),
+            FileError::Format {
+                line: Some(line),
+                message,
+            } => write!(f, "Line {}: {}", line, message),
+            FileError::Format {
+                line: None,
+                message,
+            } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Io(err) => Some(err),
+            FileError::Format { .. } => None,
+        }
+    }
+}
+
+/// The delimiter for files that hold delimited text rather than the native format,
+/// going by the file's extension
+pub fn delimiter(path: &Path) -> Option<char> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "csv" => Some(','),
+        "tsv" | "tab" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// Save in the format the file name asks for. Delimited files get the raw contents, so
+/// formulas survive being opened again.
+pub fn save(state: &State, path: &Path) -> Result<(), FileError> {
+    let text = match delimiter(path) {
+        Some(delimiter) => csv::write(state, delimiter, Export::Raw),
+        None => write_sheet(state),
+    };
+
+    Ok(write_atomic(path, &text)?)
+}
+
+/// What the sheet holds that delimited text has no room for, and so would be lost by
+/// saving it as such
+pub fn delimited_losses(state: &State) -> Vec<&'static str> {
+    let mut lost = Vec::new();
+    let aligned = state
+        .cells()
+        .any(|(_, cell)| cell.alignment == Alignment::Right);
+
+    if aligned {
+        lost.push("alignment");
+    }
+    if state
+        .column_widths
+        .iter()
+        .any(|&width| width != DEFAULT_WIDTH)
+    {
+        lost.push("column widths");
+    }
+    if state
+        .row_heights
+        .iter()
+        .any(|&height| height != DEFAULT_HEIGHT)
+    {
+        lost.push("row heights");
+    }
+    if state.iteration.is_some() {
+        lost.push("iteration settings");
+    }
+
+    lost
+}
+
+/// Open a sheet, or start a blank one if the file doesn't exist yet
+pub fn open(path: &Path) -> Result<State, FileError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(State::blank()),
+        Err(err) => return Err(err.into()),
+    };
+
+    match delimiter(path) {
+        Some(delimiter) => csv::read(&text, delimiter),
+        None => read_sheet(&text),
+    }
+}
+
+/// Replace the contents of the sheet with a delimited file, as a change that can be undone
+pub fn import(state: &mut State, path: &Path, delimiter: char) -> Result<(), FileError> {
+    let text = fs::read_to_string(path)?;
+    let records = csv::parse(&text, delimiter)?;
+
+    csv::import(state, records);
+    Ok(())
+}
+
+/// Write the sheet to a delimited file, as its raw contents or its computed results
+pub fn export(
+    state: &State,
+    path: &Path,
+    delimiter: char,
+    export: Export,
+) -> Result<(), FileError> {
+    Ok(write_atomic(path, &csv::write(state, delimiter, export))?)
+}
+
+// Write to a temporary file next to `path` and move it into place, so the file is never
+// left half written if saving fails part way through
+fn write_atomic(path: &Path, text: &str) -> io::Result<()> {
+    let name = path.file_name().ok_or(io::ErrorKind::InvalidInput)?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let result = fs::write(&temp, text).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// The sheet in the native format
+pub fn write_sheet(state: &State) -> String {
+    let mut text = format!("{} {}", HEADER, VERSION);
+    if let Some(iteration) = state.iteration {
+        text += &format!(
+            " iterations={} tolerance={}",
+            iteration.max_iterations, iteration.tolerance
+        );
+    }
+    text += "\n";
+
+    for (c, width) in state.column_widths.iter().enumerate() {
+        if *width != DEFAULT_WIDTH {
+            let column = format_column(c as u16);
+            text += &format!("{}:{} width={}\t\n", column, column, width);
+        }
+    }
+    for (r, height) in state.row_heights.iter().enumerate() {
+        if *height != DEFAULT_HEIGHT {
+            text += &format!("{}:{} height={}\t\n", r + 1, r + 1, height);
+        }
+    }
+
+    for (addr, cell) in state.cells() {
+        let mut attributes = Vec::new();
+        if cell.alignment == Alignment::Right {
+            attributes.push("align=right");
+        }
+
+        if cell.value.is_empty() && attributes.is_empty() {
+            continue;
+        }
+
+        text += &format_reference(addr);
+        for attribute in attributes {
+            text += &format!(" {}", attribute);
+        }
+        text += &format!("\t{}\n", escape(&cell.value));
+    }
+
+    text
+}
+
+/// A sheet from text in the native format. Opening it isn't part of its history.
+pub fn read_sheet(text: &str) -> Result<State, FileError> {
+    let mut lines = text.lines().enumerate();
+
+    let mut header = lines
+        .next()
+        .map(|(_, line)| line)
+        .unwrap_or_default()
+        .split(' ');
+    match (header.next(), header.next()) {
+        (Some(HEADER), Some(version)) if version.parse() == Ok(VERSION) => {}
+        (Some(HEADER), Some(version)) => {
+            return Err(FileError::format(format!(
+                "Unsupported version {}",
+                version
+            )))
+        }
+        _ => return Err(FileError::format("Not a cell file")),
+    }
+
+    let mut state = State::blank();
+
+    let mut iteration = None;
+    for attribute in header {
+        if let Some((key @ ("iterations" | "tolerance"), value)) = attribute.split_once('=') {
+            iteration
+                .get_or_insert_with(Iteration::default)
+                .set(key, value)
+                .map_err(|err| FileError::at_line(1, err))?;
+        }
+    }
+    state.set_iteration(iteration);
+
+    for (idx, line) in lines {
+        if line.is_empty() {
+            continue;
+        }
+
+        let err = |message: String| FileError::at_line(idx + 1, message);
+
+        let Some((head, value)) = line.split_once('\t') else {
+            return Err(err("Missing value".to_string()));
+        };
+
+        let mut head = head.split(' ');
+        let reference = head.next().unwrap();
+
+        if reference.contains(':') {
+            let range = resolve_range(reference).map_err(|e| err(e.message))?;
+            let size = |size: &str| size.parse().map_err(|_| err(format!("Bad size {}", size)));
+
+            for attribute in head {
+                match attribute.split_once('=') {
+                    // whole columns run through every row
+                    Some(("width", width)) if range.start.0 == 0 && range.end.0 == u16::MAX => {
+                        let width = size(width)?;
+                        for c in range.start.1..=range.end.1 {
+                            state.set_column_width(c, width);
+                        }
+                    }
+                    Some(("height", height)) if range.start.1 == 0 && range.end.1 == u16::MAX => {
+                        let height = size(height)?;
+                        for r in range.start.0..=range.end.0 {
+                            state.set_row_height(r, height);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        let addr = resolve_reference(reference).map_err(|e| err(e.message))?;
+        let mut cell = DisplayCell::new(unescape(value));
+
+        for attribute in head {
+            match attribute.split_once('=') {
+                Some(("align", "left")) => cell.alignment = Alignment::Left,
+                Some(("align", "right")) => cell.alignment = Alignment::Right,
+                Some(("align", other)) => return Err(err(format!("Unknown alignment {}", other))),
+                _ => {}
+            }
+        }
+
+        state.set_at(addr, cell);
+    }
+
+    // opening a file isn't something to undo
+    state.history = History::new();
+    Ok(state)
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for char in value.chars() {
+        match char {
+            '\\' => escaped += "\\\\",
+            '\t' => escaped += "\\t",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut state = State::blank();
+        state.set_at((0, 0), DisplayCell::new("=4 * ( 2 + 3 )".to_string()));
+        state.set_at(
+            (1, 1),
+            DisplayCell::new("Total".to_string()).with_alignment(Alignment::Right),
+        );
+        state.set_at((2, 27), DisplayCell::new("a\tb\\n\nc".to_string()));
+        state.set_at(
+            (3, 0),
+            DisplayCell::blank().with_alignment(Alignment::Right),
+        );
+
+        state.set_column_width(27, 12);
+        state.set_row_height(1, 3);
+
+        let text = write_sheet(&state);
+        assert_eq!(
+            text,
+            "cell 1\nAB:AB width=12\t\n2:2 height=3\t\nA1\t=4 * ( 2 + 3 )\nB2 align=right\tTotal\nAB3\ta\\tb\\\\n\\nc\nA4 align=right\t\n"
+        );
+
+        let read = read_sheet(&text).unwrap();
+        for addr in [(0, 0), (1, 1), (2, 27), (3, 0), (5, 5)] {
+            assert_eq!(read.get_at(addr).value, state.get_at(addr).value);
+            assert_eq!(read.get_at(addr).alignment, state.get_at(addr).alignment);
+        }
+        assert_eq!(read.column_widths, state.column_widths);
+        assert_eq!(read.row_heights, state.row_heights);
+        assert!(!read.history.is_modified());
+    }
+
+    #[test]
+    fn test_iteration() {
+        let mut state = State::blank();
+        state.set_iteration(Some(Iteration {
+            max_iterations: 50,
+            tolerance: 0.01,
+        }));
+
+        let text = write_sheet(&state);
+        assert_eq!(text, "cell 1 iterations=50 tolerance=0.01\n");
+        assert_eq!(read_sheet(&text).unwrap().iteration, state.iteration);
+
+        // settings left out keep their defaults
+        let read = read_sheet("cell 1 iterations=20\n").unwrap();
+        assert_eq!(
+            read.iteration,
+            Some(Iteration {
+                max_iterations: 20,
+                ..Iteration::default()
+            })
+        );
+        assert!(!read.history.is_modified());
+        assert_eq!(read_sheet("cell 1\n").unwrap().iteration, None);
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = std::env::temp_dir().join(format!("cell-test-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sheet.cell");
+
+        let mut state = State::blank();
+        state.set_at((0, 0), DisplayCell::new("1".to_string()));
+        save(&state, &path).unwrap();
+        state.set_at((0, 0), DisplayCell::new("2".to_string()));
+        save(&state, &path).unwrap();
+
+        // the file is replaced whole, and no temporary file is left behind next to it
+        assert_eq!(fs::read_to_string(&path).unwrap(), "cell 1\nA1\t2\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // nor when saving fails
+        assert!(save(&state, &dir.join("missing").join("sheet.cell")).is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_delimited_losses() {
+        let mut state = State::blank();
+        state.set_at((0, 0), DisplayCell::new("1".to_string()));
+        assert!(delimited_losses(&state).is_empty());
+
+        state.set_at(
+            (1, 1),
+            DisplayCell::blank().with_alignment(Alignment::Right),
+        );
+        state.set_row_height(2, 3);
+        assert_eq!(delimited_losses(&state), vec!["alignment", "row heights"]);
+
+        // sizes set back to the default aren't lost
+        state.set_row_height(2, DEFAULT_HEIGHT);
+        state.set_column_width(1, 12);
+        state.set_iteration(Some(Iteration::default()));
+        assert_eq!(
+            delimited_losses(&state),
+            vec!["alignment", "column widths", "iteration settings"]
+        );
+    }
+
+    #[test]
+    fn test_delimiter() {
+        assert_eq!(delimiter(Path::new("data.csv")), Some(','));
+        assert_eq!(delimiter(Path::new("DATA.TSV")), Some('\t'));
+        assert_eq!(delimiter(Path::new("sheet.cell")), None);
+        assert_eq!(delimiter(Path::new("sheet")), None);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert_eq!(
+            read_sheet("").err().map(|err| err.to_string()),
+            Some("Not a cell file".to_string())
+        );
+        assert_eq!(
+            read_sheet("cell 9\n").err().map(|err| err.to_string()),
+            Some("Unsupported version 9".to_string())
+        );
+        assert_eq!(
+            read_sheet("cell 1\nA1 1").err().map(|err| err.to_string()),
+            Some("Line 2: Missing value".to_string())
+        );
+        assert_eq!(
+            read_sheet("cell 1\n\nA0\t1")
+                .err()
+                .map(|err| err.to_string()),
+            Some("Line 3: Could not parse row for reference: A0".to_string())
+        );
+
+        assert_eq!(
+            read_sheet("cell 1 tolerance=-1\n")
+                .err()
+                .map(|err| err.to_string()),
+            Some("Line 1: Bad value for tolerance: -1".to_string())
+        );
+        assert_eq!(
+            read_sheet("cell 1\nB:B width=wide\t")
+                .err()
+                .map(|err| err.to_string()),
+            Some("Line 2: Bad size wide".to_string())
+        );
+
+        // attributes from later versions are skipped
+        let state = read_sheet("cell 1\nA1 color=red\t1\nB:C width=3 color=red\t\n").unwrap();
+        assert_eq!(state.get_at((0, 0)).value, "1");
+        assert_eq!(state.column_widths, vec![7, 3, 3]);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::compute::{Iteration, Splice};
+use crate::state::{Address, Alignment};
+
+/// A reversible change to the sheet, holding what was there before and after
+#[derive(Debug, PartialEq, Clone)]
+pub enum Change {
+    /// The raw contents of a cell
+    Value {
+        /// The cell changed
+        addr: Address,
+        /// Its contents before
+        before: String,
+        /// Its contents after
+        after: String,
+    },
+    /// The alignment of a cell
+    Alignment {
+        /// The cell changed
+        addr: Address,
+        /// Its alignment before
+        before: Alignment,
+        /// Its alignment after
+        after: Alignment,
+    },
+    /// The width of a column
+    ColumnWidth {
+        /// The column changed
+        column: u16,
+        /// Its width before
+        before: u16,
+        /// Its width after
+        after: u16,
+    },
+    /// The height of a row
+    RowHeight {
+        /// The row changed
+        row: u16,
+        /// Its height before
+        before: u16,
+        /// Its height after
+        after: u16,
+    },
+    /// Moving cells for inserted or deleted rows or columns. Whatever was deleted and the
+    /// rewritten formulas are recorded as changes of their own before it.
+    Splice(Splice),
+    /// Iterative calculation switched on or off, or given other settings
+    Iteration {
+        /// The settings before, or None if it was off
+        before: Option<Iteration>,
+        /// The settings after, or None if it was switched off
+        after: Option<Iteration>,
+    },
+}
+
+/// Changes are recorded as they happen and grouped into steps, so that one undo takes
+/// back everything a single command (or a whole edit session) did
+pub struct History {
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    // changes since the last commit, which make up the next step
+    pending: Vec<Change>,
+    // the number of undo steps when the sheet was last saved, if that can be got back to
+    saved: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl History {
+    /// An empty history, counted as saved
+    pub fn new() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            pending: Vec::new(),
+            saved: Some(0),
+        }
+    }
+
+    /// Add a change to the current step. Anything that could have been redone is lost.
+    pub fn record(&mut self, change: Change) {
+        // the saved state is lost along with the steps that could redo back to it
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+        self.redo.clear();
+
+        // typing into a cell only needs its value from before the first keystroke
+        if let (
+            Some(Change::Value { addr, after, .. }),
+            Change::Value {
+                addr: next,
+                after: next_after,
+                ..
+            },
+        ) = (self.pending.last_mut(), &change)
+        {
+            if addr == next {
+                *after = next_after.clone();
+                return;
+            }
+        }
+
+        self.pending.push(change);
+    }
+
+    /// Close the current step
+    pub fn commit(&mut self) {
+        if !self.pending.is_empty() {
+            self.undo.push(std::mem::take(&mut self.pending));
+        }
+    }
+
+    /// Note that the sheet as it is now has been saved
+    pub fn mark_saved(&mut self) {
+        self.commit();
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Whether anything changed since the sheet was last saved
+    pub fn is_modified(&self) -> bool {
+        !self.pending.is_empty() || self.saved != Some(self.undo.len())
+    }
+
+    /// Drop the current step, returning its changes so they can be reverted
+    pub fn cancel(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Take back the last step, returning its changes in the order they were made
+    pub fn undo(&mut self) -> Option<Vec<Change>> {
+        self.commit();
+        let step = self.undo.pop()?;
+        self.redo.push(step.clone());
+        Some(step)
+    }
+
+    /// Make the last step taken back again, returning its changes in the order they
+    /// were made
+    pub fn redo(&mut self) -> Option<Vec<Change>> {
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(addr: Address, before: &str, after: &str) -> Change {
+        Change::Value {
+            addr,
+            before: before.to_string(),
+            after: after.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new();
+        history.record(value((0, 0), "", "1"));
+        history.record(value((0, 0), "1", "12"));
+        history.record(value((0, 1), "", "x"));
+        history.commit();
+        history.record(value((0, 0), "12", "3"));
+
+        assert_eq!(history.undo(), Some(vec![value((0, 0), "12", "3")]));
+        assert_eq!(
+            history.undo(),
+            Some(vec![value((0, 0), "", "12"), value((0, 1), "", "x")])
+        );
+        assert_eq!(history.undo(), None);
+
+        assert_eq!(
+            history.redo(),
+            Some(vec![value((0, 0), "", "12"), value((0, 1), "", "x")])
+        );
+
+        // anything new clears what could be redone
+        history.record(value((1, 1), "", "y"));
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.cancel(), vec![value((1, 1), "", "y")]);
+        assert_eq!(
+            history.undo(),
+            Some(vec![value((0, 0), "", "12"), value((0, 1), "", "x")])
+        );
+    }
+
+    #[test]
+    fn test_modified() {
+        let mut history = History::new();
+        assert!(!history.is_modified());
+
+        history.record(value((0, 0), "", "1"));
+        assert!(history.is_modified());
+        history.mark_saved();
+        assert!(!history.is_modified());
+
+        // undoing and redoing back to the saved state
+        history.undo();
+        assert!(history.is_modified());
+        history.redo();
+        assert!(!history.is_modified());
+
+        // a new change after undoing means the saved state can't be reached again
+        history.undo();
+        history.record(value((0, 0), "", "2"));
+        history.commit();
+        assert!(history.is_modified());
+        history.undo();
+        assert!(history.is_modified());
+    }
+}
@@ -0,0 +1,35 @@
+//! The engine behind the `cell` spreadsheet: a sheet of cells, the formula language
+//! they are written in, and the files they are kept in.
+//!
+//! A sheet is a [`State`]. Cells hold their raw contents as typed, and [`bake`] computes
+//! them, only redoing what changed since the last bake.
+//!
+//! ```
+//! use cell::{bake, resolve_reference, DisplayCell, State, Value};
+//!
+//! let mut state = State::blank();
+//! let a1 = resolve_reference("A1").unwrap();
+//! let b1 = resolve_reference("B1").unwrap();
+//! state.set_at(a1, DisplayCell::new("20".to_string()));
+//! state.set_at(b1, DisplayCell::new("=A1 * 2 + 2".to_string()));
+//! bake(&mut state);
+//!
+//! assert_eq!(state.get_at(b1).computed.value, Some(Value::Number(42.0)));
+//! ```
+//!
+//! Formulas can be parsed on their own with [`parse`], and any error met while parsing or
+//! computing is a [`ComputeError`] whose [`CellError`] kind is what the grid shows.
+
+/// Parsing formulas and computing the values of cells
+pub mod compute;
+/// Reading and writing delimited text such as CSV
+pub mod csv;
+/// Saving and opening sheets in the native file format
+pub mod file;
+/// Undo and redo of edits to a sheet
+pub mod history;
+/// The sheet: its cells, their sizes and formatting, and the edits made to them
+pub mod state;
+
+pub use compute::{bake, parse, resolve_reference, CellError, ComputeError, Value};
+pub use state::{Address, DisplayCell, State};
@@ -1,111 +1,250 @@
-use crate::screen::draw;
-use crate::state::{Cursor, DisplayCell, Mode, State};
+use crate::screen::{draw, draw_too_small, follow_cursor, page_rows, MIN_SIZE};
 use crate::status_bar::StatusBar;
-use crate::window::{screen, Frame, Window};
-use std::io;
+use crate::view::{Mode, View};
+use crate::window::{events, screen, Event, Frame, Window};
+use cell::compute;
+use cell::csv::Export;
+use cell::file;
+use cell::state::{Alignment, Cursor, DisplayCell, State};
+use std::path::PathBuf;
+use std::process;
 use termion::event::*;
-use termion::input::TermRead;
 
-mod compute;
+mod cli;
 mod screen;
-mod state;
 mod status_bar;
+mod view;
 mod window;
 
 fn main() {
-    let stdin = io::stdin();
-    let screen = &screen();
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "--convert") {
+        if let Err(err) = cli::convert(&args[2..]) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.get(1).is_some_and(|arg| arg == "--eval") {
+        match cli::eval(&args[2..]) {
+            Ok(true) => return,
+            // some cells are in error
+            Ok(false) => process::exit(2),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
 
-    let screen_size = screen.size();
-    let mut window = Frame::new(screen, (0, 0), (screen_size.0, screen_size.1 - 1));
-    let mut status_bar = Frame::new(screen, (0, screen_size.1), (screen_size.0, 1));
+    let path = args.get(1).map(PathBuf::from);
+    let mut state = match &path {
+        Some(path) => file::open(path).unwrap_or_else(|err| {
+            eprintln!("Could not open {}: {}", path.display(), err);
+            process::exit(1);
+        }),
+        None => State::blank(),
+    };
+    state.file = path;
+
+    let mut view = View::new();
+    let screen = &screen();
 
-    let mut state = State::blank();
-    state.set_at((2, 2), DisplayCell::new("4 * ( 2 + 3 )".to_string()));
     compute::bake(&mut state);
+    render(screen, &state, &view);
 
-    draw(&mut window, &state);
-    StatusBar::draw(&mut status_bar, &state);
-    window.flush();
+    // the key before this one, for commands of two keys
+    let mut last_key = None;
+    // what `i` turns iterative calculation back on with
+    let mut iteration = state.iteration.unwrap_or_default();
 
-    for c in stdin.keys() {
-        let evt = c.unwrap();
+    for event in events() {
+        let evt = match event {
+            Event::Key(key) => key,
+            Event::Resize => {
+                follow_cursor(&state, &mut view, grid_size(screen));
+                write!(screen, "{}", termion::clear::All);
+                render(screen, &state, &view);
+                continue;
+            }
+            Event::Closed => break,
+        };
+        view.message = None;
+        let cursor = view.cursor;
 
-        match state.mode {
+        match view.mode {
             Mode::Nav => match evt {
+                Key::Char('q') if state.history.is_modified() => view.mode = Mode::Quit,
                 Key::Char('q') => break,
+                Key::Char(':') => {
+                    view.command.clear();
+                    view.mode = Mode::Command;
+                }
+                Key::Ctrl('s') => {
+                    save(&mut state, &mut view, None);
+                }
                 Key::Char('=') => {
-                    if let Cursor::Single(addr) = state.cursor {
-                        state.mode = Mode::Edit;
-                        state.edit_cursor = state.get_at(addr).value.len();
+                    if let Cursor::Single(addr) = view.cursor {
+                        view.mode = Mode::Edit;
+
+                        // an empty cell starts out as a formula, since only a leading `=`
+                        // makes one
+                        if state.get_at(addr).value.is_empty() {
+                            state.edit_at(addr, |cell| {
+                                DisplayCell::new("=".to_string())
+                                    .with_alignment(cell.alignment.clone())
+                            });
+                        }
+                        view.edit_cursor = state.get_at(addr).value.len();
+                    }
+                }
+
+                Key::Char('u') => state.undo(),
+                Key::Ctrl('r') => state.redo(),
+
+                Key::Char('y') => view.clipboard = Some(state.copy(view.cursor)),
+                Key::Char('x') => view.clipboard = Some(state.cut(view.cursor)),
+                Key::Char('p') => state.paste(view.cursor, &mut view.clipboard),
+
+                Key::Ctrl('d') => state.fill_down(view.cursor),
+
+                Key::Char('i') => match state.iteration {
+                    Some(settings) => {
+                        iteration = settings;
+                        state.set_iteration(None);
                     }
+                    None => state.set_iteration(Some(iteration)),
+                },
+
+                Key::Char('w') if view.scroll.0 > 0 => view.scroll.0 -= 1,
+                Key::Char('a') if view.scroll.1 > 0 => view.scroll.1 -= 1,
+                Key::Char('s') => view.scroll.0 += 1,
+                Key::Char('d') => view.scroll.1 += 1,
+
+                Key::PageUp => {
+                    let rows = page_rows(&state, &view, grid_size(screen).1, -1);
+                    view.cursor = view.cursor.page(-(rows as i16));
+                }
+                Key::PageDown => {
+                    let rows = page_rows(&state, &view, grid_size(screen).1, 1);
+                    view.cursor = view.cursor.page(rows as i16);
+                }
+                Key::Home => view.cursor = state.home(view.cursor),
+                Key::End => view.cursor = state.end(view.cursor),
+                Key::CtrlHome => view.cursor = Cursor::Single((0, 0)),
+                Key::Char('g') if last_key == Some(Key::Char('g')) => {
+                    view.cursor = Cursor::Single((0, 0))
                 }
 
-                Key::Char('w') if state.scroll.0 > 0 => state.scroll.0 -= 1,
-                Key::Char('a') if state.scroll.1 > 0 => state.scroll.1 -= 1,
-                Key::Char('s') => state.scroll.0 += 1,
-                Key::Char('d') => state.scroll.1 += 1,
+                Key::CtrlUp => view.cursor = state.jump(view.cursor, (-1, 0)),
+                Key::CtrlDown => view.cursor = state.jump(view.cursor, (1, 0)),
+                Key::CtrlLeft => view.cursor = state.jump(view.cursor, (0, -1)),
+                Key::CtrlRight => view.cursor = state.jump(view.cursor, (0, 1)),
 
-                Key::Up => state.cursor = state.cursor.move_v(-1),
-                Key::Down => state.cursor = state.cursor.move_v(1),
-                Key::Left => state.cursor = state.cursor.move_h(-1),
-                Key::Right => state.cursor = state.cursor.move_h(1),
+                Key::Up => view.cursor = view.cursor.move_v(-1),
+                Key::Down => view.cursor = view.cursor.move_v(1),
+                Key::Left => view.cursor = view.cursor.move_h(-1),
+                Key::Right => view.cursor = view.cursor.move_h(1),
+
+                Key::ShiftUp => view.cursor = view.cursor.extend((-1, 0)),
+                Key::ShiftDown => view.cursor = view.cursor.extend((1, 0)),
+                Key::ShiftLeft => view.cursor = view.cursor.extend((0, -1)),
+                Key::ShiftRight => view.cursor = view.cursor.extend((0, 1)),
+
+                Key::Delete | Key::Backspace => state.clear(view.cursor),
+                Key::Char('<') => state.align(view.cursor, Alignment::Left),
+                Key::Char('>') => state.align(view.cursor, Alignment::Right),
+
+                // on a whole row or column
+                Key::Char('+') => state.insert_selected(view.cursor),
+                Key::Char('-') => state.delete_selected(view.cursor),
+
+                Key::Char(']') => state.resize_columns(view.cursor, 1),
+                Key::Char('[') => state.resize_columns(view.cursor, -1),
+                Key::Char('f') => state.fit_columns(view.cursor),
+                Key::Char('}') => state.resize_rows(view.cursor, 1),
+                Key::Char('{') => state.resize_rows(view.cursor, -1),
 
                 _ => {}
             },
+            Mode::Command => match evt {
+                Key::Char('\n') => {
+                    view.mode = Mode::Nav;
+                    let command = std::mem::take(&mut view.command);
+                    if run_command(&mut state, &mut view, &command) {
+                        break;
+                    }
+                }
+                Key::Esc => view.mode = Mode::Nav,
+                Key::Backspace if view.command.is_empty() => view.mode = Mode::Nav,
+                Key::Backspace => {
+                    view.command.pop();
+                }
+                Key::Char(l) => view.command.push(l),
+                _ => {}
+            },
+            Mode::Quit => match evt {
+                Key::Char('y') if save(&mut state, &mut view, None) => break,
+                Key::Char('n') => break,
+                _ => view.mode = Mode::Nav,
+            },
             Mode::Edit => {
-                let Cursor::Single(addr) = state.cursor else {
+                let Cursor::Single(addr) = view.cursor else {
                     panic!("Non-single cursor in EDIT mode");
                 };
 
                 match evt {
-                    Key::Char('\n') | Key::Esc => state.mode = Mode::Nav,
-                    Key::Ctrl('a') => state.edit_cursor = 0,
-                    Key::Ctrl('e') => state.edit_cursor = state.get_at(addr).value.len(),
+                    Key::Char('\n') => view.mode = Mode::Nav,
+                    Key::Esc => {
+                        state.cancel_edit();
+                        view.mode = Mode::Nav;
+                    }
+                    Key::Ctrl('a') => view.edit_cursor = 0,
+                    Key::Ctrl('e') => view.edit_cursor = state.get_at(addr).value.len(),
                     Key::Alt('f') => {
-                        state.edit_cursor = state.get_at(addr).value[state.edit_cursor..]
+                        view.edit_cursor = state.get_at(addr).value[view.edit_cursor..]
                             .find(" ")
-                            .and_then(|idx| Some(idx + state.edit_cursor + 1))
+                            .map(|idx| idx + view.edit_cursor + 1)
                             .unwrap_or(state.get_at(addr).value.len())
                     }
                     Key::Alt('b') => {
-                        state.edit_cursor = state.get_at(addr).value[..state.edit_cursor]
+                        view.edit_cursor = state.get_at(addr).value[..view.edit_cursor]
                             .rfind(" ")
                             .and_then(|idx| if idx == 0 { None } else { Some(idx - 1) })
                             .unwrap_or(0)
                     }
 
                     Key::Char(l) => {
-                        let edit_cursor = state.edit_cursor;
+                        let edit_cursor = view.edit_cursor;
                         state.edit_at(addr, |cell| {
                             let mut new_val = cell.value.clone();
                             new_val.insert(edit_cursor, l);
-                            DisplayCell::new(new_val)
+                            DisplayCell::new(new_val).with_alignment(cell.alignment.clone())
                         });
-                        state.edit_cursor += 1;
+                        view.edit_cursor += 1;
                     }
 
                     Key::Backspace => {
-                        let edit_cursor = state.edit_cursor;
+                        let edit_cursor = view.edit_cursor;
                         state.edit_at(addr, |cell| {
                             let mut new_val = cell.value.clone();
 
-                            if edit_cursor == 0 {
-                                return DisplayCell::new(new_val);
+                            if edit_cursor > 0 {
+                                new_val.remove(edit_cursor - 1);
                             }
 
-                            new_val.remove(edit_cursor - 1);
-                            DisplayCell::new(new_val)
+                            DisplayCell::new(new_val).with_alignment(cell.alignment.clone())
                         });
 
-                        if state.edit_cursor > 0 {
-                            state.edit_cursor -= 1;
+                        if view.edit_cursor > 0 {
+                            view.edit_cursor -= 1;
                         }
                     }
 
-                    Key::Left if state.edit_cursor > 0 => state.edit_cursor -= 1,
-                    Key::Right if state.edit_cursor < state.get_at(addr).value.len() => {
-                        state.edit_cursor += 1
+                    Key::Left if view.edit_cursor > 0 => view.edit_cursor -= 1,
+                    Key::Right if view.edit_cursor < state.get_at(addr).value.len() => {
+                        view.edit_cursor += 1
                     }
 
                     _ => {}
@@ -113,17 +252,192 @@ fn main() {
             }
         }
 
+        // everything done since the last key in Nav mode (or during a whole edit) is
+        // undone in one go
+        if let Mode::Nav = view.mode {
+            state.history.commit();
+        }
+
+        // keep the cursor on screen, but leave scrolling by hand alone
+        if view.cursor != cursor {
+            follow_cursor(&state, &mut view, grid_size(screen));
+        }
+        last_key = match last_key {
+            Some(Key::Char('g')) if evt == Key::Char('g') => None,
+            _ => Some(evt),
+        };
+
         compute::bake(&mut state);
-        draw(&mut window, &state);
-        StatusBar::draw(&mut status_bar, &state);
-        window.flush();
+        render(screen, &state, &view);
+    }
+}
+
+// The size of the grid, which is the whole screen apart from the status bar
+fn grid_size(screen: &dyn Window) -> (u16, u16) {
+    let (width, height) = screen.size();
+    (width, height.saturating_sub(1))
+}
+
+// Draw the grid with the status bar along the bottom, sized to fit the screen as it is now
+fn render(screen: &dyn Window, state: &State, view: &View) {
+    let (width, height) = screen.size();
+
+    if width < MIN_SIZE.0 || height < MIN_SIZE.1 + 1 {
+        draw_too_small(screen, (MIN_SIZE.0, MIN_SIZE.1 + 1));
+    } else {
+        let window = Frame::new(screen, (0, 0), (width, height - 1));
+        let mut status_bar = Frame::new(screen, (0, height - 1), (width, 1));
+
+        draw(&window, state, view);
+        StatusBar::draw(&mut status_bar, state, view);
+    }
+
+    screen.flush();
+}
+
+// Save to `path`, or to the file the sheet was opened from. Returns whether all of it
+// saved, so that quitting after a save to delimited text that dropped something stops.
+fn save(state: &mut State, view: &mut View, path: Option<&str>) -> bool {
+    if let Some(path) = path {
+        state.file = Some(PathBuf::from(path));
+    }
+
+    let Some(path) = state.file.clone() else {
+        view.message = Some("No file name, save with :w <file>".to_string());
+        return false;
+    };
+
+    // delimited text can't hold everything, so the sheet isn't counted as saved if
+    // something was left out
+    let lost = match file::delimiter(&path) {
+        Some(_) => file::delimited_losses(state),
+        None => vec![],
+    };
+
+    match file::save(state, &path) {
+        Ok(()) if !lost.is_empty() => {
+            view.message = Some(format!(
+                "Saved {} without its {}",
+                path.display(),
+                lost.join(", ")
+            ));
+            false
+        }
+        Ok(()) => {
+            state.history.mark_saved();
+            view.message = Some(format!("Saved {}", path.display()));
+            true
+        }
+        Err(err) => {
+            view.message = Some(format!("Could not save {}: {}", path.display(), err));
+            false
+        }
     }
 }
 
+// Run a `:` command, returning whether to quit
+fn run_command(state: &mut State, view: &mut View, command: &str) -> bool {
+    let command = command.trim();
+    let (name, arg) = match command.split_once(' ') {
+        Some((name, arg)) => (name, Some(arg.trim())),
+        None => (command, None),
+    };
+
+    match name {
+        "w" => {
+            save(state, view, arg);
+            false
+        }
+        "wq" | "x" => save(state, view, arg),
+        "import" | "export" if arg.is_none() => {
+            view.message = Some(format!("Usage: :{} <file>", name));
+            false
+        }
+        "import" => {
+            let path = PathBuf::from(arg.unwrap());
+            let delimiter = file::delimiter(&path).unwrap_or(',');
+            view.message = Some(match file::import(state, &path, delimiter) {
+                Ok(()) => format!("Imported {}", path.display()),
+                Err(err) => format!("Could not import {}: {}", path.display(), err),
+            });
+            false
+        }
+        "export" => {
+            // `:export <file> raw` writes formulas rather than their results
+            let (path, export) = match arg.unwrap().strip_suffix(" raw") {
+                Some(path) => (PathBuf::from(path.trim()), Export::Raw),
+                None => (PathBuf::from(arg.unwrap()), Export::Computed),
+            };
+            let delimiter = file::delimiter(&path).unwrap_or(',');
+            view.message = Some(match file::export(state, &path, delimiter, export) {
+                Ok(()) => format!("Exported {}", path.display()),
+                Err(err) => format!("Could not export {}: {}", path.display(), err),
+            });
+            false
+        }
+        "set" => {
+            if let Err(err) = set(state, arg.unwrap_or_default()) {
+                view.message = Some(err);
+            }
+            false
+        }
+        "q" if state.history.is_modified() => {
+            view.mode = Mode::Quit;
+            false
+        }
+        "q" | "q!" => true,
+        "" => false,
+        _ => {
+            view.message = Some(format!("Unknown command :{}", command));
+            false
+        }
+    }
+}
+
+// Change settings given as `key=value`. Setting `iterations` or `tolerance` turns on
+// iterative calculation, and `iterations=off` turns it off.
+fn set(state: &mut State, settings: &str) -> Result<(), String> {
+    if settings.is_empty() {
+        return Err("Usage: :set iterations=<n> tolerance=<x>".to_string());
+    }
+
+    let mut iteration = Some(state.iteration.unwrap_or_default());
+    for setting in settings.split_whitespace() {
+        match setting.split_once('=') {
+            Some(("iterations", "off")) => iteration = None,
+            Some((key, value)) => iteration
+                .get_or_insert_with(compute::Iteration::default)
+                .set(key, value)?,
+            None => return Err(format!("Expected <setting>=<value>, got {}", setting)),
+        }
+    }
+
+    state.set_iteration(iteration);
+    Ok(())
+}
+
 // Shortcuts
-//  q - quit
+//  q - quit, asking to save any changes
+//  : - run a command (:w [file], :wq, :q, :q!, :import <file>, :export <file> [raw],
+//      :set iterations=<n|off> tolerance=<x>)
+//  Ctrl-s - save
 //  = - edit
+//  u / Ctrl-r - undo / redo
+//  y / x / p - copy, cut and paste the selection
+//  Ctrl-d - fill selection down
+//  i - toggle iterative calculation of cycles, keeping its settings
 //
 //  wasd - scroll
 //  arrow keys - move selection
+//  shift + arrow keys - extend selection
+//  ctrl + arrow keys - jump to the edge of the filled cells
+//  PageUp / PageDown - move selection a screen up / down
+//  Home / End - first column / last filled cell of the row
+//  Ctrl-Home / gg - top left of the sheet
+//  delete - clear selection
+//  < / > - align selection left / right
+//  + / - - insert / delete the selected rows or columns
+//  [ / ] - narrow / widen the selected columns
+//  f - fit the selected columns to their contents
+//  { / } - shorten / heighten the selected rows
 //
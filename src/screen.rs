@@ -1,5 +1,8 @@
-use crate::state::{Address, Alignment, Cursor, DisplayCell, Mode, State};
+use crate::view::{Mode, View};
 use crate::window::Window;
+use cell::compute::format_column;
+use cell::state::{Address, Alignment, Cursor, DisplayCell, State};
+use std::collections::HashMap;
 use termion::color;
 use termion::style;
 
@@ -11,32 +14,52 @@ enum Position<'a> {
         bottom_right: Address,
     },
 
-    ColumnHeader(u16, u16),
-    RowHeader(u16, u16),
-    InsideCell(Address, &'a DisplayCell, u16),
+    ColumnHeader(u16, Track),
+    RowHeader(u16, Track, Track),
+    InsideCell(Address, &'a DisplayCell, Track, Track),
 }
 
-pub fn draw(window: &dyn Window, state: &State) {
-    let State { cursor, scroll, .. } = state;
+// How far into a column (or row) a character on screen is, and how wide (or tall) the
+// column is. At the border after the column, the offset is the same as the width.
+#[derive(Clone, Copy)]
+struct Track {
+    offset: u16,
+    size: u16,
+}
+
+// The smallest window that fits the headers and one cell
+pub const MIN_SIZE: (u16, u16) = (12, 4);
+
+const ROW_HEADER_WIDTH: u16 = 3;
+const COLUMN_HEADER_HEIGHT: u16 = 1;
+
+pub fn draw(window: &dyn Window, state: &State, view: &View) {
+    let View { cursor, scroll, .. } = view;
     let (width, height) = window.size();
 
     let screen_sel = match cursor {
-        Cursor::Single((r, c)) => (r + 1, c + 1),
+        Cursor::Single((r, c)) | Cursor::Range(_, (r, c)) => (r + 1, c + 1),
         Cursor::Row(r) => (*r + 1, 0),
         Cursor::Column(c) => (0, *c + 1),
     };
 
-    for y in 0..height {
-        window.go_to(1, y + 1);
+    let rows = layout(height, COLUMN_HEADER_HEIGHT, scroll.0, |r| {
+        state.row_height(r)
+    });
+    let cols = layout(width, ROW_HEADER_WIDTH, scroll.1, |c| state.column_width(c));
 
-        for x in 0..width {
-            // position on screen
-            let row = if y < 2 { 0 } else { 1 + (scroll.0 + y - 2) / 2 };
-            let col = if x < 4 { 0 } else { 1 + (scroll.1 + x - 4) / 8 };
+    // each visible cell is formatted the first time one of its characters is drawn
+    let mut texts: HashMap<Address, Vec<char>> = HashMap::new();
+
+    for (y, &(row, line)) in rows.iter().enumerate() {
+        window.go_to(1, y as u16 + 1);
+
+        // the row and column on the other side of a border, which aren't the next ones
+        // along after the headers when scrolled
+        let next_row = rows.get(y + 1).map_or(row + 1, |next| next.0);
 
-            let y = if row == 0 { y } else { y + scroll.0 };
-            let x = if col == 0 { x } else { x + scroll.1 };
-            let text_pos = (x + 4) % 8;
+        for (x, &(col, char)) in cols.iter().enumerate() {
+            let next_col = cols.get(x + 1).map_or(col + 1, |next| next.0);
 
             // APPLY STYLING TO HEADER
             if row == 0 || col == 0 {
@@ -55,76 +78,101 @@ pub fn draw(window: &dyn Window, state: &State) {
                 )
             };
 
+            let between_cols = char.offset == char.size;
+            let between_rows = line.offset == line.size;
+
             use Position::*;
-            let position = if x % 8 == 3 && y % 2 == 1 {
+            let position = if between_cols && between_rows {
                 Corner {
                     top_left: (row, col),
-                    bottom_right: (row + 1, col + 1),
+                    bottom_right: (next_row, next_col),
                 }
-            } else if x % 8 == 3 {
-                BetweenCols((row, col), (row, col + 1))
-            } else if y % 2 == 1 {
-                BetweenRows((row, col), (row + 1, col))
-            } else if y < 2 {
-                ColumnHeader(col, text_pos)
-            } else if x < 3 {
-                RowHeader(row, text_pos)
+            } else if between_cols {
+                BetweenCols((row, col), (row, next_col))
+            } else if between_rows {
+                BetweenRows((row, col), (next_row, col))
+            } else if row == 0 {
+                ColumnHeader(col, char)
+            } else if col == 0 {
+                RowHeader(row, line, char)
             } else {
                 let cell = state.get_at((row - 1, col - 1));
-                InsideCell((row - 1, col - 1), cell, text_pos)
+                InsideCell((row - 1, col - 1), cell, line, char)
             };
 
+            // fill in the cells of a range selection
+            if let (InsideCell((r, c), ..), Cursor::Range(..)) = (&position, cursor) {
+                let (start, end) = cursor.bounds();
+                if (start.0..=end.0).contains(r) && (start.1..=end.1).contains(c) {
+                    write!(window, "{}", color::Bg(color::LightCyan));
+                }
+            }
+
             let val = match position {
-                Corner { top_left: addr, .. } if addr == screen_sel => "╃",
+                Corner { top_left: addr, .. } if addr == screen_sel => '╃',
                 Corner {
                     top_left: (r, _),
                     bottom_right: (_, c),
-                } if (r, c) == screen_sel => "╄",
+                } if (r, c) == screen_sel => '╄',
                 Corner {
                     top_left: (_, c),
                     bottom_right: (r, _),
-                } if (r, c) == screen_sel => "╅",
+                } if (r, c) == screen_sel => '╅',
                 Corner {
                     bottom_right: addr, ..
-                } if addr == screen_sel => "╆",
-                Corner { .. } => "┼",
+                } if addr == screen_sel => '╆',
+                Corner { .. } => '┼',
 
-                BetweenCols(addr, _) | BetweenCols(_, addr) if addr == screen_sel => "┃",
-                BetweenCols(..) => "│",
-                BetweenRows(addr, _) | BetweenRows(_, addr) if addr == screen_sel => "━",
-                BetweenRows(..) => "─",
+                BetweenCols(addr, _) | BetweenCols(_, addr) if addr == screen_sel => '┃',
+                BetweenCols(..) => '│',
+                BetweenRows(addr, _) | BetweenRows(_, addr) if addr == screen_sel => '━',
+                BetweenRows(..) => '─',
 
-                ColumnHeader(col, text_pos) => &State::col_name(col as u8)
+                ColumnHeader(0, _) => ' ',
+                ColumnHeader(col, char) => format_column(col - 1)
                     .chars()
-                    .nth(text_pos as usize)
-                    .unwrap_or(' ')
-                    .to_string(),
+                    .nth(char.offset as usize)
+                    .unwrap_or(' '),
 
-                RowHeader(row, text_pos) => &row
+                // row numbers are on the first line of the row, to the right
+                RowHeader(row, line, char) if line.offset == 0 => row
                     .to_string()
                     .chars()
-                    .nth_back(6 - text_pos as usize)
-                    .unwrap_or(' ')
-                    .to_string(),
+                    .nth_back((char.size - 1 - char.offset) as usize)
+                    .unwrap_or(' '),
+                RowHeader(..) => ' ',
 
-                InsideCell(cell_addr, cell, text_pos) => {
-                    let is_sole_selection = match state.cursor {
+                InsideCell(cell_addr, cell, line, char) => {
+                    let is_sole_selection = match view.cursor {
                         Cursor::Single(addr) => addr == cell_addr,
                         _ => false,
                     };
 
-                    let mut chars = match state.mode {
-                        Mode::Edit if is_sole_selection => &cell.value,
-                        _ => &cell.computed.display,
-                    }
-                    .chars();
+                    let text = texts.entry(cell_addr).or_insert_with(|| match view.mode {
+                        Mode::Edit if is_sole_selection => cell.value.chars().collect(),
+                        _ => cell
+                            .computed
+                            .display_in(char.size as usize)
+                            .chars()
+                            .collect(),
+                    });
+
+                    // text too long for the cell wraps onto its other lines
+                    let start = ((line.offset * char.size) as usize).min(text.len());
+                    let end = (start + char.size as usize).min(text.len());
+                    let chars = &text[start..end];
 
                     let l = match &cell.alignment {
-                        Alignment::Left => chars.nth(text_pos as usize),
-                        Alignment::Right => chars.nth_back(6 - text_pos as usize),
+                        Alignment::Left => chars.get(char.offset as usize).copied(),
+                        Alignment::Right => {
+                            let pad = char.size as usize - chars.len();
+                            (char.offset as usize)
+                                .checked_sub(pad)
+                                .map(|idx| chars[idx])
+                        }
                     };
 
-                    &l.unwrap_or(' ').to_string()
+                    l.unwrap_or(' ')
                 }
             };
 
@@ -132,3 +180,343 @@ pub fn draw(window: &dyn Window, state: &State) {
         }
     }
 }
+
+// Where each of `length` characters along one axis of the screen falls: in which
+// column (or row), counting the header as 0 and the rest from 1, and how far into it.
+// Everything past the header is moved along by `scroll` characters.
+fn layout(length: u16, header: u16, scroll: u16, size: impl Fn(u16) -> u16) -> Vec<(u16, Track)> {
+    let mut positions = Vec::new();
+
+    let mut idx = 0;
+    let mut track = Track {
+        offset: 0,
+        size: header,
+    };
+    let advance = |idx: &mut u16, track: &mut Track| {
+        track.offset += 1;
+        if track.offset > track.size {
+            *idx = idx.saturating_add(1);
+            *track = Track {
+                offset: 0,
+                size: size(*idx - 1),
+            };
+        }
+    };
+
+    for p in 0..length {
+        if p == header + 1 {
+            for _ in 0..scroll {
+                advance(&mut idx, &mut track);
+            }
+        }
+
+        positions.push((idx, track));
+        advance(&mut idx, &mut track);
+    }
+
+    positions
+}
+
+// Scroll a window of the given size just far enough to show the cell (or the row or
+// column) the cursor is on, along with its borders
+pub fn follow_cursor(state: &State, view: &mut View, (width, height): (u16, u16)) {
+    let (row, col) = match view.cursor {
+        Cursor::Single((r, c)) | Cursor::Range(_, (r, c)) => (Some(r), Some(c)),
+        Cursor::Row(r) => (Some(r), None),
+        Cursor::Column(c) => (None, Some(c)),
+    };
+
+    if let Some(r) = row {
+        let area = height.saturating_sub(COLUMN_HEADER_HEIGHT + 1);
+        let start = start_of(r, |r| state.row_height(r));
+        view.scroll.0 = follow(view.scroll.0, area, start, state.row_height(r));
+    }
+
+    if let Some(c) = col {
+        let area = width.saturating_sub(ROW_HEADER_WIDTH + 1);
+        let start = start_of(c, |c| state.column_width(c));
+        view.scroll.1 = follow(view.scroll.1, area, start, state.column_width(c));
+    }
+}
+
+// How many rows fit in a window `height` lines tall, going up (or down) from the
+// cursor's row. Always at least one.
+pub fn page_rows(state: &State, view: &View, height: u16, direction: i16) -> u16 {
+    let (Cursor::Single((row, _)) | Cursor::Range(_, (row, _)) | Cursor::Row(row)) = view.cursor
+    else {
+        return 1;
+    };
+
+    let area = height.saturating_sub(COLUMN_HEADER_HEIGHT + 1);
+    let mut used = 0;
+    let mut rows = 0;
+    while let Some(r) = row.checked_add_signed(direction * rows as i16) {
+        used += state.row_height(r) + 1;
+        if used > area || rows == i16::MAX as u16 {
+            break;
+        }
+        rows += 1;
+    }
+
+    rows.max(1)
+}
+
+// How many characters into the scrollable part of the grid a column (or row) starts
+fn start_of(idx: u16, size: impl Fn(u16) -> u16) -> u16 {
+    let start: u32 = (0..idx).map(|i| size(i) as u32 + 1).sum();
+    start.min(u16::MAX as u32) as u16
+}
+
+// The scroll that shows `size` characters from `start` and the border after them within
+// `area`, moving as little as it can. Something too big to fit is shown from its start.
+fn follow(scroll: u16, area: u16, start: u16, size: u16) -> u16 {
+    let end = start.saturating_add(size + 1);
+    if start < scroll {
+        start
+    } else if end > scroll.saturating_add(area) {
+        end.saturating_sub(area).min(start)
+    } else {
+        scroll
+    }
+}
+
+// Shown in place of everything else when the terminal is smaller than `needed`, wrapped
+// onto as many lines as it takes
+pub fn draw_too_small(window: &dyn Window, needed: (u16, u16)) {
+    let (width, height) = window.size();
+    let message = format!("Make the terminal at least {}x{}", needed.0, needed.1);
+
+    write!(window, "{}", style::Reset);
+    for y in 0..height {
+        window.go_to(1, y + 1);
+
+        for x in 0..width {
+            let idx = (y * width + x) as usize;
+            write!(window, "{}", message.chars().nth(idx).unwrap_or(' '));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::window::virtual_screen::VirtualScreen;
+    use cell::compute::bake;
+
+    const HEADER: Option<u8> = Some(8);
+    const CELL: Option<u8> = Some(7);
+    const SELECTED: Option<u8> = Some(14);
+
+    // A few cells of text, numbers and formulas, shared with the status bar tests
+    pub fn sheet() -> State {
+        let mut state = State::blank();
+        state.set_at((0, 0), DisplayCell::new("12".to_string()));
+        state.set_at((0, 1), DisplayCell::new("Hello world".to_string()));
+        state.set_at((1, 1), DisplayCell::new("=A1 * 2.5".to_string()));
+        state.set_at(
+            (2, 0),
+            DisplayCell::new("=1 / 3".to_string()).with_alignment(Alignment::Right),
+        );
+        bake(&mut state);
+        state
+    }
+
+    fn render(state: &State, view: &View) -> VirtualScreen {
+        let screen = VirtualScreen::new((30, 8));
+        draw(&screen, state, view);
+        screen
+    }
+
+    #[test]
+    fn test_draw() {
+        let screen = render(&sheet(), &View::new());
+
+        assert_eq!(
+            screen.text(),
+            "   │A      │B      │C      │D
+───┼───────┼───────┼───────┼──
+  1│12     │Hello w│       │
+───┼───────╆━━━━━━━╅───────┼──
+  2│       ┃30     ┃       │
+───┼───────╄━━━━━━━╃───────┼──
+  3│0.33333│       │       │
+───┼───────┼───────┼───────┼──"
+        );
+
+        assert_eq!(screen.cell_at(1, 1).bg, HEADER);
+        assert_eq!(screen.cell_at(1, 3).bg, HEADER);
+        assert_eq!(screen.cell_at(5, 3).bg, CELL);
+        assert_eq!(screen.cell_at(5, 3).fg, Some(0));
+    }
+
+    #[test]
+    fn test_draw_scrolled() {
+        let state = sheet();
+        let mut view = View::new();
+        view.cursor = Cursor::Single((0, 0));
+        view.scroll = (2, 8);
+
+        // A1 is scrolled off, border and all
+        assert_eq!(
+            render(&state, &view).text(),
+            "   │B      │C      │D      │E
+───┼───────┼───────┼───────┼──
+  2│30     │       │       │
+───┼───────┼───────┼───────┼──
+  3│       │       │       │
+───┼───────┼───────┼───────┼──
+  4│       │       │       │
+───┼───────┼───────┼───────┼──"
+        );
+    }
+
+    #[test]
+    fn test_draw_range() {
+        let state = sheet();
+        let mut view = View::new();
+        view.cursor = Cursor::Range((2, 1), (1, 0));
+        let screen = render(&state, &view);
+
+        // the border follows the cell the selection was extended to
+        assert_eq!(
+            screen.text(),
+            "   │A      │B      │C      │D
+───┼───────┼───────┼───────┼──
+  1│12     │Hello w│       │
+───╆━━━━━━━╅───────┼───────┼──
+  2┃       ┃30     │       │
+───╄━━━━━━━╃───────┼───────┼──
+  3│0.33333│       │       │
+───┼───────┼───────┼───────┼──"
+        );
+
+        // A2:B3 is filled in
+        assert_eq!(screen.cell_at(5, 3).bg, CELL);
+        assert_eq!(screen.cell_at(5, 5).bg, SELECTED);
+        assert_eq!(screen.cell_at(13, 7).bg, SELECTED);
+        assert_eq!(screen.cell_at(21, 5).bg, CELL);
+    }
+
+    #[test]
+    fn test_draw_row_and_column() {
+        let state = sheet();
+        let mut view = View::new();
+        view.cursor = Cursor::Row(1);
+        assert_eq!(
+            render(&state, &view).text(),
+            "   │A      │B      │C      │D
+───┼───────┼───────┼───────┼──
+  1│12     │Hello w│       │
+━━━╅───────┼───────┼───────┼──
+  2┃       │30     │       │
+━━━╃───────┼───────┼───────┼──
+  3│0.33333│       │       │
+───┼───────┼───────┼───────┼──"
+        );
+
+        view.cursor = Cursor::Column(2);
+        let column = render(&state, &view).text();
+        assert!(column.starts_with("   │A      │B      ┃C      ┃D\n───┼───────┼───────╄━━━━━━━╃──"));
+    }
+
+    #[test]
+    fn test_draw_edit() {
+        let state = sheet();
+        let mut view = View::new();
+        view.mode = Mode::Edit;
+
+        // the cell being edited shows what was typed rather than its result
+        let text = render(&state, &view).text();
+        assert!(text.contains("  2│       ┃=A1 * 2┃"));
+        assert!(text.contains("  1│12     │Hello w│"));
+    }
+
+    #[test]
+    fn test_draw_sizes() {
+        let mut state = sheet();
+        let mut view = View::new();
+        state.set_column_width(0, 3);
+        state.set_column_width(1, 12);
+        state.set_row_height(0, 2);
+        state.set_at(
+            (0, 2),
+            DisplayCell::new("'Wraps around".to_string()).with_alignment(Alignment::Right),
+        );
+        bake(&mut state);
+
+        // long text wraps onto the lines of taller rows
+        let screen = VirtualScreen::new((30, 9));
+        draw(&screen, &state, &view);
+        assert_eq!(
+            screen.text(),
+            "   │A  │B           │C      │D
+───┼───┼────────────┼───────┼─
+  1│12 │Hello world │Wraps a│
+   │   │            │  round│
+───┼───╆━━━━━━━━━━━━╅───────┼─
+  2│   ┃30          ┃       │
+───┼───╄━━━━━━━━━━━━╃───────┼─
+  3│0.3│            │       │
+───┼───┼────────────┼───────┼─"
+        );
+
+        view.scroll = (3, 4);
+        draw(&screen, &state, &view);
+        assert!(screen.text().starts_with(
+            "   │B           │C      │D
+───╆━━━━━━━━━━━━╅───────┼─────
+  2┃30          ┃       │"
+        ));
+    }
+
+    #[test]
+    fn test_follow_cursor() {
+        let mut state = sheet();
+        let mut view = View::new();
+        state.set_column_width(1, 12);
+
+        // on screen already
+        follow_cursor(&state, &mut view, (30, 8));
+        assert_eq!(view.scroll, (0, 0));
+
+        // C5 and its bottom border end on the 10th line and 29th character past the headers
+        view.cursor = Cursor::Single((4, 2));
+        follow_cursor(&state, &mut view, (30, 8));
+        assert_eq!(view.scroll, (4, 3));
+        assert_eq!(
+            render(&state, &view).text(),
+            "   │    │B           │C      │
+───┼────┼────────────┼───────┼
+  3│3333│            │       │
+───┼────┼────────────┼───────┼
+  4│    │            │       │
+───┼────┼────────────╆━━━━━━━╅
+  5│    │            ┃       ┃
+───┼────┼────────────╄━━━━━━━╃"
+        );
+
+        view.cursor = Cursor::Column(0);
+        follow_cursor(&state, &mut view, (30, 8));
+        assert_eq!(view.scroll, (4, 0));
+        view.cursor = Cursor::Row(1);
+        follow_cursor(&state, &mut view, (30, 8));
+        assert_eq!(view.scroll, (2, 0));
+
+        assert_eq!(page_rows(&state, &view, 8, 1), 3);
+        state.set_row_height(2, 5);
+        assert_eq!(page_rows(&state, &view, 8, 1), 1);
+        assert_eq!(page_rows(&state, &view, 8, -1), 2);
+    }
+
+    #[test]
+    fn test_draw_too_small() {
+        let screen = VirtualScreen::new((11, 4));
+        draw(&screen, &sheet(), &View::new());
+
+        // the grid drawn before is cleared
+        draw_too_small(&screen, (12, 5));
+        assert_eq!(screen.text(), "Make the te\nrminal at l\neast 12x5\n");
+        assert_eq!(screen.cell_at(1, 1).bg, None);
+        assert_eq!(screen.cell_at(5, 4).bg, None);
+    }
+}
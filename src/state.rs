@@ -1,126 +1,736 @@
+use crate::compute::{
+    fit_number, shift_cell, splice_cell, Axis, ComputeError, DependencyGraph, Iteration, Splice,
+    Value,
+};
+use crate::history::{Change, History};
+use std::path::PathBuf;
 use std::sync::OnceLock;
 
+/// A sheet: the contents of its cells and their sizes, along with the history of edits
+/// made to them. Cells are read and written with [`State::get_at`] and [`State::set_at`].
 pub struct State {
-    pub mode: Mode,
-    pub content: Vec<Vec<DisplayCell>>,
-    pub scroll: Address,
-    pub cursor: Cursor,
-    pub edit_cursor: usize,
+    content: Vec<Vec<DisplayCell>>,
+    /// In characters, not counting borders; columns past the end of these are the
+    /// default width
+    pub column_widths: Vec<u16>,
+    /// In lines, not counting borders; rows past the end of these are the default height
+    pub row_heights: Vec<u16>,
+    pub(crate) graph: DependencyGraph,
+    /// When set, cycles are solved by iteration rather than flagged as errors
+    pub iteration: Option<Iteration>,
+    /// The edits that can be undone and redone
+    pub history: History,
+    /// Where the sheet is saved
+    pub file: Option<PathBuf>,
 }
 
 impl State {
+    /// An empty sheet with nothing to undo, not yet saved anywhere
     pub fn blank() -> Self {
         State {
-            mode: Mode::Nav,
             content: Vec::new(),
-            scroll: (0, 0),
-            cursor: Cursor::Single((1, 1)),
-            edit_cursor: 0,
+            column_widths: Vec::new(),
+            row_heights: Vec::new(),
+            graph: DependencyGraph::new(),
+            iteration: None,
+            history: History::new(),
+            file: None,
         }
     }
 
-    pub fn col_name(i: u8) -> String {
-        ((('A' as u8 - 1) + (i % 26)) as char).to_string()
+    /// Switch iterative calculation on or off, which needs the whole sheet recomputed
+    pub fn set_iteration(&mut self, iteration: Option<Iteration>) {
+        if self.iteration != iteration {
+            self.history.record(Change::Iteration {
+                before: self.iteration,
+                after: iteration,
+            });
+            self.iteration = iteration;
+            self.graph.invalidate();
+        }
     }
 
-    pub fn edit_at<F>(&mut self, (r, c): Address, f: F)
+    /// Replace the cell at `addr` with one made from what's there now
+    pub fn edit_at<F>(&mut self, addr: Address, f: F)
     where
         F: Fn(&DisplayCell) -> DisplayCell,
     {
-        let mut row = &mut self.get_row(r as usize);
-        let cell = State::get_col(&mut row, c as usize);
-
-        row[c as usize] = f(cell)
+        let cell = f(self.get_at(addr));
+        self.set_at(addr, cell);
     }
 
-    fn get_row(&mut self, r: usize) -> &mut Vec<DisplayCell> {
-        while r >= self.content.len() {
+    // The cell at `addr`, growing the sheet to fit it
+    fn cell_mut(&mut self, (r, c): Address) -> &mut DisplayCell {
+        while r as usize >= self.content.len() {
             self.content.push(vec![])
         }
 
-        &mut self.content[r]
-    }
-
-    fn get_col(row: &mut Vec<DisplayCell>, c: usize) -> &DisplayCell {
-        while c >= row.len() {
+        let row = &mut self.content[r as usize];
+        while c as usize >= row.len() {
             row.push(DisplayCell::blank())
         }
 
-        &row[c]
+        &mut row[c as usize]
     }
 
+    /// The cell at `addr`, which is blank if nothing was ever put there
     pub fn get_at(&self, (row, col): Address) -> &DisplayCell {
-        &self
-            .content
+        self.content
             .get(row as usize)
             .and_then(|x| x.get(col as usize))
-            .unwrap_or(BLANK_CELL.get_or_init(|| DisplayCell::blank()))
+            .unwrap_or(BLANK_CELL.get_or_init(DisplayCell::blank))
     }
 
-    pub fn set_at(&mut self, (r, c): Address, cell: DisplayCell) {
-        let row = &mut self.get_row(r as usize);
-        while c as usize >= row.len() {
-            row.push(DisplayCell::blank())
+    /// Every cell the sheet holds, row by row, along with its address. The cells past
+    /// these are blank.
+    pub fn cells(&self) -> impl Iterator<Item = (Address, &DisplayCell)> + '_ {
+        self.content.iter().enumerate().flat_map(|(r, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(c, cell)| ((r as u16, c as u16), cell))
+        })
+    }
+
+    /// The addresses of the cells the sheet holds between two corners (inclusive), row
+    /// by row
+    pub fn cells_in(&self, (start, end): (Address, Address)) -> Vec<Address> {
+        let mut cells = Vec::new();
+
+        let last_row = (self.content.len() as u16).min(end.0.saturating_add(1));
+        for r in start.0..last_row {
+            let last_col = (self.content[r as usize].len() as u16).min(end.1.saturating_add(1));
+            for c in start.1..last_col {
+                cells.push((r, c));
+            }
+        }
+
+        cells
+    }
+
+    /// The computed value of an existing cell, which can be updated without marking the
+    /// cell as edited
+    pub fn computation_mut(&mut self, (r, c): Address) -> Option<&mut CellComputation> {
+        self.content
+            .get_mut(r as usize)
+            .and_then(|row| row.get_mut(c as usize))
+            .map(|cell| &mut cell.computed)
+    }
+
+    /// Put a cell at `addr`, growing the sheet to fit it. The change is recorded in the
+    /// history, and the cell is recomputed (along with whatever reads it) by the next bake.
+    pub fn set_at(&mut self, addr: Address, cell: DisplayCell) {
+        let before = self.get_at(addr).clone();
+        if before.value != cell.value {
+            self.history.record(Change::Value {
+                addr,
+                before: before.value,
+                after: cell.value.clone(),
+            });
+        }
+        if before.alignment != cell.alignment {
+            self.history.record(Change::Alignment {
+                addr,
+                before: before.alignment,
+                after: cell.alignment.clone(),
+            });
+        }
+
+        self.graph.mark_dirty(addr);
+        *self.cell_mut(addr) = cell;
+    }
+
+    /// Take back the last step of the history
+    pub fn undo(&mut self) {
+        if let Some(step) = self.history.undo() {
+            for change in step.iter().rev() {
+                self.apply(change, true);
+            }
         }
+    }
 
-        row[c as usize] = cell;
+    /// Make the last step that was undone again
+    pub fn redo(&mut self) {
+        if let Some(step) = self.history.redo() {
+            for change in step.iter() {
+                self.apply(change, false);
+            }
+        }
+    }
+
+    /// Put the cell back the way it was before editing started
+    pub fn cancel_edit(&mut self) {
+        for change in self.history.cancel().iter().rev() {
+            self.apply(change, true);
+        }
+    }
+
+    // Make (or take back) a recorded change without recording it again
+    fn apply(&mut self, change: &Change, undo: bool) {
+        match change {
+            Change::Value {
+                addr,
+                before,
+                after,
+            } => {
+                self.graph.mark_dirty(*addr);
+                self.cell_mut(*addr).value = if undo { before } else { after }.clone();
+            }
+            Change::Alignment {
+                addr,
+                before,
+                after,
+            } => self.cell_mut(*addr).alignment = if undo { before } else { after }.clone(),
+            Change::ColumnWidth {
+                column,
+                before,
+                after,
+            } => {
+                let width = if undo { before } else { after };
+                resize(&mut self.column_widths, *column, *width, DEFAULT_WIDTH);
+            }
+            Change::RowHeight { row, before, after } => {
+                let height = if undo { before } else { after };
+                resize(&mut self.row_heights, *row, *height, DEFAULT_HEIGHT);
+            }
+            Change::Splice(splice) => self.move_cells(match undo {
+                true => splice.inverse(),
+                false => *splice,
+            }),
+            Change::Iteration { before, after } => {
+                self.iteration = if undo { *before } else { *after };
+                self.graph.invalidate();
+            }
+        }
     }
 }
 
-pub enum Mode {
-    Nav,
-    Edit,
+impl State {
+    /// Insert a blank row (or column) in place of the selected one, moving it and
+    /// everything after it along
+    pub fn insert_selected(&mut self, cursor: Cursor) {
+        match cursor {
+            Cursor::Row(at) => self.splice(Splice::Insert {
+                axis: Axis::Row,
+                at,
+                count: 1,
+            }),
+            Cursor::Column(at) => self.splice(Splice::Insert {
+                axis: Axis::Column,
+                at,
+                count: 1,
+            }),
+            _ => {}
+        }
+    }
+
+    /// Delete the selected row (or column), moving everything after it back. Anything
+    /// other than a whole row or column is left alone.
+    pub fn delete_selected(&mut self, cursor: Cursor) {
+        match cursor {
+            Cursor::Row(at) => self.splice(Splice::Delete {
+                axis: Axis::Row,
+                at,
+                count: 1,
+            }),
+            Cursor::Column(at) => self.splice(Splice::Delete {
+                axis: Axis::Column,
+                at,
+                count: 1,
+            }),
+            _ => {}
+        }
+    }
+
+    /// Insert or delete rows or columns, rewriting every formula in the sheet to follow
+    /// the cells it refers to
+    pub fn splice(&mut self, splice: Splice) {
+        let cells: Vec<Address> = self.cells().map(|(addr, _)| addr).collect();
+
+        // empty what's deleted first, so that undoing the splice can put it back
+        if let Splice::Delete { axis, at, count } = splice {
+            for &addr in &cells {
+                let cell = self.get_at(addr);
+                let is_blank = cell.value.is_empty() && cell.alignment == Alignment::Left;
+                if splice.address(addr).is_none() && !is_blank {
+                    self.set_at(addr, DisplayCell::blank());
+                }
+            }
+
+            for idx in at..at.saturating_add(count) {
+                match axis {
+                    Axis::Row => self.set_row_height(idx, DEFAULT_HEIGHT),
+                    Axis::Column => self.set_column_width(idx, DEFAULT_WIDTH),
+                }
+            }
+        }
+
+        for addr in cells {
+            let cell = self.get_at(addr);
+            let value = splice_cell(&cell.value, splice);
+            if value != cell.value {
+                let alignment = cell.alignment.clone();
+                self.set_at(addr, DisplayCell::new(value).with_alignment(alignment));
+            }
+        }
+
+        self.history.record(Change::Splice(splice));
+        self.move_cells(splice);
+    }
+
+    // Move the cells (and sizes) of the sheet for a splice, without touching formulas
+    fn move_cells(&mut self, splice: Splice) {
+        match splice.axis() {
+            Axis::Row => {
+                splice_vec(&mut self.content, splice, Vec::new());
+                splice_vec(&mut self.row_heights, splice, DEFAULT_HEIGHT);
+            }
+            Axis::Column => {
+                for row in &mut self.content {
+                    splice_vec(row, splice, DisplayCell::blank());
+                }
+                splice_vec(&mut self.column_widths, splice, DEFAULT_WIDTH);
+            }
+        }
+
+        // every cell after the splice has a new address
+        self.graph.invalidate();
+    }
 }
 
+// Insert blanks into, or remove items from, a row of cells (or the rows of the sheet, or
+// their sizes). Anything pushed past the last row or column is lost.
+fn splice_vec<T: Clone>(items: &mut Vec<T>, splice: Splice, blank: T) {
+    match splice {
+        Splice::Insert { at, count, .. } if (at as usize) < items.len() => {
+            let blanks = std::iter::repeat_n(blank, count as usize);
+            items.splice(at as usize..at as usize, blanks);
+            items.truncate(u16::MAX as usize + 1);
+        }
+        Splice::Delete { at, count, .. } if (at as usize) < items.len() => {
+            let end = (at as usize + count as usize).min(items.len());
+            items.drain(at as usize..end);
+        }
+        _ => {}
+    }
+}
+
+impl State {
+    /// The width of a column in characters
+    pub fn column_width(&self, column: u16) -> u16 {
+        let width = self.column_widths.get(column as usize);
+        width.copied().unwrap_or(DEFAULT_WIDTH)
+    }
+
+    /// The height of a row in lines
+    pub fn row_height(&self, row: u16) -> u16 {
+        let height = self.row_heights.get(row as usize);
+        height.copied().unwrap_or(DEFAULT_HEIGHT)
+    }
+
+    /// Set the width of a column, between 1 and MAX_WIDTH
+    pub fn set_column_width(&mut self, column: u16, width: u16) {
+        let before = self.column_width(column);
+        let after = width.clamp(1, MAX_WIDTH);
+        if before != after {
+            self.history.record(Change::ColumnWidth {
+                column,
+                before,
+                after,
+            });
+            resize(&mut self.column_widths, column, after, DEFAULT_WIDTH);
+        }
+    }
+
+    /// Set the height of a row, between 1 and MAX_HEIGHT
+    pub fn set_row_height(&mut self, row: u16, height: u16) {
+        let before = self.row_height(row);
+        let after = height.clamp(1, MAX_HEIGHT);
+        if before != after {
+            self.history
+                .record(Change::RowHeight { row, before, after });
+            resize(&mut self.row_heights, row, after, DEFAULT_HEIGHT);
+        }
+    }
+
+    /// Widen (or narrow) every column the selection covers
+    pub fn resize_columns(&mut self, cursor: Cursor, by: i16) {
+        for column in selected_columns(cursor) {
+            let width = self.column_width(column).saturating_add_signed(by);
+            self.set_column_width(column, width);
+        }
+    }
+
+    /// Make every row the selection covers taller (or shorter)
+    pub fn resize_rows(&mut self, cursor: Cursor, by: i16) {
+        for row in selected_rows(cursor) {
+            let height = self.row_height(row).saturating_add_signed(by);
+            self.set_row_height(row, height);
+        }
+    }
+
+    /// Make the selected columns as wide as their widest computed value, or the default
+    /// width if they're empty
+    pub fn fit_columns(&mut self, cursor: Cursor) {
+        for column in selected_columns(cursor) {
+            let widest = self
+                .content
+                .iter()
+                .filter_map(|row| row.get(column as usize))
+                .map(|cell| cell.computed.display.chars().count() as u16)
+                .max()
+                .unwrap_or(0);
+
+            let width = if widest == 0 { DEFAULT_WIDTH } else { widest };
+            self.set_column_width(column, width);
+        }
+    }
+}
+
+// A selected row covers every column, so can't be resized across
+fn selected_columns(cursor: Cursor) -> Vec<u16> {
+    let (start, end) = cursor.bounds();
+    match cursor {
+        Cursor::Row(_) => Vec::new(),
+        _ => (start.1..=end.1).collect(),
+    }
+}
+
+fn selected_rows(cursor: Cursor) -> Vec<u16> {
+    let (start, end) = cursor.bounds();
+    match cursor {
+        Cursor::Column(_) => Vec::new(),
+        _ => (start.0..=end.0).collect(),
+    }
+}
+
+impl State {
+    /// Where the cursor moves to at the far edge of the block of filled cells it's in, or
+    /// at the next filled cell if it's already at an edge. With nothing further up or left,
+    /// that's the edge of the sheet; with nothing further down or right, it stays.
+    pub fn jump(&self, cursor: Cursor, (rows, cols): (i16, i16)) -> Cursor {
+        let (Cursor::Single(start) | Cursor::Range(_, start)) = cursor else {
+            return cursor;
+        };
+
+        let filled = |addr: Address| !self.get_at(addr).value.is_empty();
+        let widest = self.content.iter().map(|row| row.len()).max().unwrap_or(0);
+        let step = |(r, c): Address| -> Option<Address> {
+            let next = (r.checked_add_signed(rows)?, c.checked_add_signed(cols)?);
+            // past the last row or column with anything in it
+            match (rows > 0 && next.0 as usize >= self.content.len())
+                || (cols > 0 && next.1 as usize >= widest)
+            {
+                true => None,
+                false => Some(next),
+            }
+        };
+
+        let mut addr = start;
+        if step(start).is_some_and(|next| filled(start) && filled(next)) {
+            while let Some(next) = step(addr).filter(|next| filled(*next)) {
+                addr = next;
+            }
+        } else {
+            while let Some(next) = step(addr) {
+                addr = next;
+                if filled(next) {
+                    break;
+                }
+            }
+
+            if !filled(addr) && (rows > 0 || cols > 0) {
+                addr = start;
+            }
+        }
+
+        Cursor::Single(addr)
+    }
+
+    /// The first column of the cursor's row
+    pub fn home(&self, cursor: Cursor) -> Cursor {
+        match cursor {
+            Cursor::Single((r, _)) | Cursor::Range(_, (r, _)) => Cursor::Single((r, 0)),
+            Cursor::Column(_) => Cursor::Column(0),
+            Cursor::Row(r) => Cursor::Row(r),
+        }
+    }
+
+    /// The last filled cell of the cursor's row
+    pub fn end(&self, cursor: Cursor) -> Cursor {
+        let last = |row: &Vec<DisplayCell>| row.iter().rposition(|cell| !cell.value.is_empty());
+
+        match cursor {
+            Cursor::Single((r, _)) | Cursor::Range(_, (r, _)) => {
+                let row = self.content.get(r as usize);
+                Cursor::Single((r, row.and_then(last).unwrap_or(0) as u16))
+            }
+            Cursor::Column(_) => {
+                let widest = self.content.iter().filter_map(last).max();
+                Cursor::Column(widest.unwrap_or(0) as u16)
+            }
+            Cursor::Row(r) => Cursor::Row(r),
+        }
+    }
+}
+
+// Set the size at `idx`, filling in any before it with the default
+fn resize(sizes: &mut Vec<u16>, idx: u16, size: u16, default: u16) {
+    if idx as usize >= sizes.len() {
+        sizes.resize(idx as usize + 1, default);
+    }
+    sizes[idx as usize] = size;
+}
+
+impl State {
+    /// Empty every cell of the selection, keeping its formatting
+    pub fn clear(&mut self, cursor: Cursor) {
+        for addr in self.cells_in(cursor.bounds()) {
+            let cell = self.get_at(addr);
+            if !cell.value.is_empty() {
+                let blank = DisplayCell::blank().with_alignment(cell.alignment.clone());
+                self.set_at(addr, blank);
+            }
+        }
+    }
+
+    /// Align every cell of the selection
+    pub fn align(&mut self, cursor: Cursor, alignment: Alignment) {
+        for addr in self.cells_in(cursor.bounds()) {
+            let before = self.get_at(addr).alignment.clone();
+            if before != alignment {
+                self.history.record(Change::Alignment {
+                    addr,
+                    before,
+                    after: alignment.clone(),
+                });
+                self.cell_mut(addr).alignment = alignment.clone();
+            }
+        }
+    }
+
+    /// Fill the selection down from its top row, or a single row from the row above it.
+    /// Relative references move down with each copy.
+    pub fn fill_down(&mut self, cursor: Cursor) {
+        let (start, end) = cursor.bounds();
+
+        let (source, last_row) = if start.0 == end.0 {
+            let Some(source) = start.0.checked_sub(1) else {
+                return;
+            };
+            (source, end.0)
+        } else {
+            let last_row = end.0.min(self.content.len().saturating_sub(1) as u16);
+            (start.0, last_row)
+        };
+
+        let width = self.content.get(source as usize).map_or(0, |row| row.len()) as u16;
+        if width == 0 {
+            return;
+        }
+
+        for c in start.1..=end.1.min(width - 1) {
+            let value = self.get_at((source, c)).value.clone();
+            for r in source + 1..=last_row {
+                let value = shift_cell(&value, ((r - source) as i32, 0));
+                if !value.is_empty() || !self.get_at((r, c)).value.is_empty() {
+                    let alignment = self.get_at((r, c)).alignment.clone();
+                    self.set_at((r, c), DisplayCell::new(value).with_alignment(alignment));
+                }
+            }
+        }
+    }
+
+    /// Copy the raw contents of the selection, as far as it holds anything
+    pub fn copy(&self, cursor: Cursor) -> Clipboard {
+        let (start, end) = cursor.bounds();
+
+        let last_row = end
+            .0
+            .min(self.content.len().saturating_sub(1) as u16)
+            .max(start.0);
+        let widest = (start.0..=last_row)
+            .map(|r| self.content.get(r as usize).map_or(0, |row| row.len()))
+            .max()
+            .unwrap_or(0);
+        let last_col = end.1.min(widest.saturating_sub(1) as u16).max(start.1);
+
+        let cells = (start.0..=last_row)
+            .map(|r| {
+                (start.1..=last_col)
+                    .map(|c| self.get_at((r, c)).value.clone())
+                    .collect()
+            })
+            .collect();
+
+        Clipboard {
+            origin: start,
+            cells,
+            cut: false,
+        }
+    }
+
+    /// Copy the selection and clear it; pasting then moves the cells
+    pub fn cut(&mut self, cursor: Cursor) -> Clipboard {
+        let mut clipboard = self.copy(cursor);
+        clipboard.cut = true;
+
+        let origin = clipboard.origin;
+        let size = (clipboard.cells.len(), clipboard.cells[0].len());
+        for r in 0..size.0 {
+            for c in 0..size.1 {
+                let addr = (origin.0 + r as u16, origin.1 + c as u16);
+                if !self.get_at(addr).value.is_empty() {
+                    self.set_at(addr, DisplayCell::blank());
+                }
+            }
+        }
+
+        clipboard
+    }
+
+    /// Paste the clipboard with its top left corner at the selection. Copied formulas have
+    /// their relative references moved by the distance they were pasted from where they
+    /// were copied; cut cells are moved as they are, and can only be pasted once, which
+    /// empties the clipboard.
+    pub fn paste(&mut self, cursor: Cursor, clipboard: &mut Option<Clipboard>) {
+        let Some(copied) = clipboard else {
+            return;
+        };
+
+        let (target, _) = cursor.bounds();
+        let offset = (
+            target.0 as i32 - copied.origin.0 as i32,
+            target.1 as i32 - copied.origin.1 as i32,
+        );
+
+        let mut pasted = Vec::new();
+        for (r, row) in copied.cells.iter().enumerate() {
+            for (c, value) in row.iter().enumerate() {
+                let (Some(row), Some(col)) = (
+                    target.0.checked_add(r as u16),
+                    target.1.checked_add(c as u16),
+                ) else {
+                    continue;
+                };
+
+                let value = match copied.cut {
+                    true => value.clone(),
+                    false => shift_cell(value, offset),
+                };
+                pasted.push(((row, col), value));
+            }
+        }
+
+        if copied.cut {
+            *clipboard = None;
+        }
+
+        for (addr, value) in pasted {
+            if !value.is_empty() || !self.get_at(addr).value.is_empty() {
+                self.set_at(addr, DisplayCell::new(value));
+            }
+        }
+    }
+}
+
+/// Raw cell contents copied from the sheet
+pub struct Clipboard {
+    /// The top left corner of the copied cells
+    pub origin: Address,
+    /// Rows of copied cells, starting from the origin
+    pub cells: Vec<Vec<String>>,
+    /// Whether the cells were cut, so pasting moves them rather than copying
+    pub cut: bool,
+}
+
+/// The row and column of a cell, counting from 0
 pub type Address = (u16, u16);
 
+/// The width of a column that hasn't been resized
+pub const DEFAULT_WIDTH: u16 = 7;
+/// The height of a row that hasn't been resized
+pub const DEFAULT_HEIGHT: u16 = 1;
+/// The widest a column can be made
+pub const MAX_WIDTH: u16 = 60;
+/// The tallest a row can be made
+pub const MAX_HEIGHT: u16 = 10;
+
 static BLANK_CELL: OnceLock<DisplayCell> = OnceLock::new();
 
+/// What a cell computed to, filled in by `bake`
 #[derive(Clone)]
 pub struct CellComputation {
+    /// Whether the rest is up to date with the cell's contents
     pub is_computed: bool,
-    pub error: bool,
+    /// Why the cell has no value, if it failed to compute
+    pub error: Option<ComputeError>,
+    /// The text shown for the cell, or its error code
     pub display: String,
-    pub value: Option<f32>,
+    /// The value other cells read, unless there was an error
+    pub value: Option<Value>,
+}
+
+impl Default for CellComputation {
+    fn default() -> Self {
+        CellComputation::new()
+    }
 }
 
 impl CellComputation {
+    /// Nothing computed yet
     pub fn new() -> Self {
         CellComputation {
             is_computed: false,
-            error: false,
+            error: None,
             display: "".to_string(),
             value: None,
         }
     }
 
+    /// Mark the cell as needing to be computed again
     pub fn clear(&mut self) {
         self.is_computed = false;
     }
 
-    pub fn set_error(&mut self, err: String) {
+    /// Record that the cell failed to compute
+    pub fn set_error(&mut self, err: ComputeError) {
         self.is_computed = true;
-        self.error = true;
-        self.display = err;
+        self.display = err.kind.code().to_string();
+        self.error = Some(err);
         self.value = None;
     }
 
-    pub fn set_computed(&mut self, value: f32) {
+    /// Record the value the cell computed to
+    pub fn set_computed(&mut self, value: Value) {
         self.is_computed = true;
-        self.error = false;
-        self.display = format!("{}", value);
+        self.error = None;
+        self.display = value.to_string();
         self.value = Some(value);
     }
+
+    /// The display text for a cell `width` characters wide, with numbers rounded to fit
+    pub fn display_in(&self, width: usize) -> String {
+        match self.value {
+            Some(Value::Number(num)) => fit_number(num, width),
+            _ => self.display.clone(),
+        }
+    }
 }
 
+/// A cell's raw contents (`value`), as typed, and what they computed to
 #[derive(Clone)]
 pub struct DisplayCell {
+    /// Which side of the cell its text is drawn against
     pub alignment: Alignment,
+    /// The contents as typed
     pub value: String,
+    /// What the contents computed to
     pub computed: CellComputation,
 }
 
 impl DisplayCell {
+    /// A left-aligned cell holding `value` as typed, which isn't computed until the next
+    /// bake
     pub fn new(value: String) -> Self {
         DisplayCell {
             value,
@@ -129,32 +739,76 @@ impl DisplayCell {
         }
     }
 
+    /// An empty cell
     pub fn blank() -> Self {
         DisplayCell::new("".to_string())
     }
 
+    /// The same cell aligned differently
     pub fn with_alignment(mut self, alignment: Alignment) -> Self {
         self.alignment = alignment;
         self
     }
 }
 
-#[derive(Clone)]
+/// Which side of a cell its text is drawn against
+#[derive(Debug, PartialEq, Clone)]
 pub enum Alignment {
+    /// Against the left border
     Left,
+    /// Against the right border
     Right,
 }
 
+/// What's selected in the sheet
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Cursor {
+    /// One cell
     Single(Address),
-    // Range(Address, Address),
+    /// The cell the selection was started from, and the cell it was extended to
+    Range(Address, Address),
+    /// A whole row
     Row(u16),
+    /// A whole column
     Column(u16),
 }
 
 impl Cursor {
+    /// The top left and bottom right corners of the selection
+    pub fn bounds(&self) -> (Address, Address) {
+        match self {
+            Cursor::Single(addr) => (*addr, *addr),
+            Cursor::Range(anchor, head) => (
+                (anchor.0.min(head.0), anchor.1.min(head.1)),
+                (anchor.0.max(head.0), anchor.1.max(head.1)),
+            ),
+            Cursor::Row(r) => ((*r, 0), (*r, u16::MAX)),
+            Cursor::Column(c) => ((0, *c), (u16::MAX, *c)),
+        }
+    }
+
+    /// Grow or shrink a selection from the cell it was started at
+    pub fn extend(&self, (rows, cols): (i16, i16)) -> Self {
+        let (anchor, head) = match self {
+            Cursor::Single(addr) => (*addr, *addr),
+            Cursor::Range(anchor, head) => (*anchor, *head),
+            _ => return *self,
+        };
+
+        let head = (
+            head.0.saturating_add_signed(rows),
+            head.1.saturating_add_signed(cols),
+        );
+        match head == anchor {
+            true => Cursor::Single(anchor),
+            false => Cursor::Range(anchor, head),
+        }
+    }
+
+    /// Move left or right by a cell, selecting the whole row when moving left of column A
     pub fn move_h(&self, direction: i16) -> Self {
         match self {
+            Cursor::Range(_, head) => Cursor::Single(*head).move_h(direction),
             Cursor::Single((r, c)) if direction < 0 && *c == 0 => Cursor::Row(*r),
             Cursor::Single((r, c)) => Cursor::Single((*r, c.saturating_add_signed(direction))),
             Cursor::Row(r) if direction < 0 => Cursor::Row(*r), // copy of self
@@ -163,8 +817,21 @@ impl Cursor {
         }
     }
 
+    /// Move up or down by a page of rows, stopping at the top row rather than going on
+    /// to select a whole column like move_v
+    pub fn page(&self, rows: i16) -> Self {
+        match self {
+            Cursor::Range(_, head) => Cursor::Single(*head).page(rows),
+            Cursor::Single((r, c)) => Cursor::Single((r.saturating_add_signed(rows), *c)),
+            Cursor::Row(r) => Cursor::Row(r.saturating_add_signed(rows)),
+            Cursor::Column(c) => Cursor::Column(*c),
+        }
+    }
+
+    /// Move up or down by a cell, selecting the whole column when moving above row 1
     pub fn move_v(&self, direction: i16) -> Self {
         match self {
+            Cursor::Range(_, head) => Cursor::Single(*head).move_v(direction),
             Cursor::Single((r, c)) if direction < 0 && *r == 0 => Cursor::Column(*c),
             Cursor::Single((r, c)) => Cursor::Single((r.saturating_add_signed(direction), *c)),
             Cursor::Row(r) => Cursor::Row(r.saturating_add_signed(direction)),
@@ -173,3 +840,280 @@ impl Cursor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with(cells: &[(Address, &str)]) -> State {
+        let mut state = State::blank();
+        for (addr, value) in cells {
+            state.set_at(*addr, DisplayCell::new(value.to_string()));
+        }
+        state
+    }
+
+    #[test]
+    fn test_copy_paste() {
+        // B1 = A1 * 2
+        let mut state = state_with(&[((0, 0), "3"), ((0, 1), "=A1 * 2")]);
+        let mut clipboard = Some(state.copy(Cursor::Single((0, 1))));
+
+        state.paste(Cursor::Single((1, 1)), &mut clipboard);
+        assert_eq!(state.get_at((1, 1)).value, "=A2 * 2");
+
+        // pasting again still works, and references off the grid become #REF!
+        state.paste(Cursor::Single((0, 0)), &mut clipboard);
+        assert_eq!(state.get_at((0, 0)).value, "=#REF! * 2");
+        assert_eq!(state.get_at((0, 1)).value, "=A1 * 2");
+    }
+
+    #[test]
+    fn test_copy_paste_row() {
+        let mut state = state_with(&[((0, 0), "1"), ((0, 1), "=A1 + $A$1"), ((0, 2), "Text")]);
+        let clipboard = state.copy(Cursor::Row(0));
+        assert_eq!(clipboard.cells[0].len(), 3);
+
+        state.paste(Cursor::Single((2, 0)), &mut Some(clipboard));
+        assert_eq!(state.get_at((2, 0)).value, "1");
+        assert_eq!(state.get_at((2, 1)).value, "=A3 + $A$1");
+        assert_eq!(state.get_at((2, 2)).value, "Text");
+    }
+
+    #[test]
+    fn test_cut_paste() {
+        let mut state = state_with(&[((0, 0), "3"), ((0, 1), "=A1 * 2")]);
+        let mut clipboard = Some(state.cut(Cursor::Single((0, 1))));
+        assert_eq!(state.get_at((0, 1)).value, "");
+
+        // moved cells keep pointing at the same place
+        state.paste(Cursor::Single((4, 4)), &mut clipboard);
+        assert_eq!(state.get_at((4, 4)).value, "=A1 * 2");
+        assert!(clipboard.is_none());
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut state = state_with(&[((0, 0), "1")]);
+        state.history.commit();
+
+        // a whole edit session is one step
+        for value in ["1", "12", "123"] {
+            state.edit_at((0, 0), |_| DisplayCell::new(value.to_string()));
+        }
+        state.history.commit();
+
+        let clipboard = state.copy(Cursor::Range((0, 0), (0, 1)));
+        state.paste(Cursor::Single((1, 0)), &mut Some(clipboard));
+        state.align(Cursor::Single((1, 0)), Alignment::Right);
+        state.history.commit();
+
+        state.undo();
+        assert_eq!(state.get_at((1, 0)).value, "");
+        assert_eq!(state.get_at((1, 0)).alignment, Alignment::Left);
+        assert_eq!(state.get_at((0, 0)).value, "123");
+
+        state.undo();
+        assert_eq!(state.get_at((0, 0)).value, "1");
+
+        state.redo();
+        state.redo();
+        assert_eq!(state.get_at((0, 0)).value, "123");
+        assert_eq!(state.get_at((1, 0)).value, "123");
+        assert_eq!(state.get_at((1, 0)).alignment, Alignment::Right);
+    }
+
+    #[test]
+    fn test_cancel_edit() {
+        let mut state = state_with(&[((0, 0), "1")]);
+        state.history.commit();
+
+        state.edit_at((0, 0), |_| DisplayCell::new("15".to_string()));
+        state.edit_at((0, 0), |_| DisplayCell::new("155".to_string()));
+        state.cancel_edit();
+
+        assert_eq!(state.get_at((0, 0)).value, "1");
+
+        // the cancelled edit can't be redone, but what came before can still be undone
+        state.redo();
+        assert_eq!(state.get_at((0, 0)).value, "1");
+        state.undo();
+        assert_eq!(state.get_at((0, 0)).value, "");
+    }
+
+    #[test]
+    fn test_range_selection() {
+        let cursor = Cursor::Single((1, 1)).extend((1, 0)).extend((0, 2));
+        assert_eq!(cursor, Cursor::Range((1, 1), (2, 3)));
+        assert_eq!(cursor.bounds(), ((1, 1), (2, 3)));
+
+        // selections can grow past their start in either direction
+        let cursor = cursor.extend((-3, 0));
+        assert_eq!(cursor.bounds(), ((0, 1), (1, 3)));
+        assert_eq!(
+            Cursor::Range((1, 1), (1, 2)).extend((0, -1)),
+            Cursor::Single((1, 1))
+        );
+        assert_eq!(cursor.move_v(1), Cursor::Single((1, 3)));
+    }
+
+    #[test]
+    fn test_range_operations() {
+        let mut state = state_with(&[
+            ((0, 0), "1"),
+            ((0, 1), "=A1 * 2"),
+            ((1, 0), "5"),
+            ((3, 3), "Far away"),
+        ]);
+
+        let cursor = Cursor::Range((0, 0), (1, 1));
+        assert_eq!(
+            state.cells_in(cursor.bounds()),
+            vec![(0, 0), (0, 1), (1, 0)]
+        );
+
+        let clipboard = state.copy(cursor);
+        state.paste(Cursor::Single((4, 0)), &mut Some(clipboard));
+        assert_eq!(state.get_at((4, 1)).value, "=A5 * 2");
+        assert_eq!(state.get_at((5, 0)).value, "5");
+
+        state.fill_down(Cursor::Range((0, 1), (2, 1)));
+        assert_eq!(state.get_at((1, 1)).value, "=A2 * 2");
+        assert_eq!(state.get_at((2, 1)).value, "=A3 * 2");
+
+        state.clear(Cursor::Range((1, 1), (0, 0)));
+        assert_eq!(state.get_at((0, 0)).value, "");
+        assert_eq!(state.get_at((1, 1)).value, "");
+        assert_eq!(state.get_at((2, 1)).value, "=A3 * 2");
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut state = state_with(&[((0, 1), "Hello world"), ((2, 1), "=1 / 3")]);
+        crate::compute::bake(&mut state);
+        state.history.commit();
+
+        state.resize_columns(Cursor::Range((0, 0), (0, 2)), 2);
+        assert_eq!(state.column_widths, vec![9, 9, 9]);
+        assert_eq!(state.column_width(3), DEFAULT_WIDTH);
+        state.history.commit();
+
+        let cursor = Cursor::Single((0, 1));
+        state.fit_columns(cursor);
+        assert_eq!(state.column_width(1), 17);
+        state.resize_columns(cursor, -100);
+        assert_eq!(state.column_width(1), 1);
+        state.history.commit();
+
+        // a whole row can only be made taller
+        let cursor = Cursor::Row(3);
+        state.resize_columns(cursor, 1);
+        state.resize_rows(cursor, 1);
+        assert_eq!(state.row_height(3), 2);
+        assert_eq!(state.row_heights, vec![1, 1, 1, 2]);
+        state.history.commit();
+
+        state.undo();
+        assert_eq!(state.row_height(3), DEFAULT_HEIGHT);
+        state.undo();
+        assert_eq!(state.column_width(1), 9);
+        state.undo();
+        assert_eq!(state.column_width(1), DEFAULT_WIDTH);
+    }
+
+    #[test]
+    fn test_jump() {
+        // A1:A3 and A6 are filled, along with C1
+        let state = state_with(&[
+            ((0, 0), "1"),
+            ((1, 0), "2"),
+            ((2, 0), "3"),
+            ((5, 0), "6"),
+            ((0, 2), "Far"),
+        ]);
+
+        let cursor = state.jump(Cursor::Single((0, 0)), (1, 0));
+        assert_eq!(cursor, Cursor::Single((2, 0)));
+        let cursor = state.jump(cursor, (1, 0));
+        assert_eq!(cursor, Cursor::Single((5, 0)));
+
+        // there's nothing further down, and nothing further up is the top of the sheet
+        assert_eq!(state.jump(cursor, (1, 0)), Cursor::Single((5, 0)));
+        assert_eq!(
+            state.jump(Cursor::Single((4, 1)), (-1, 0)),
+            Cursor::Single((0, 1))
+        );
+
+        let cursor = state.jump(Cursor::Range((1, 1), (0, 0)), (0, 1));
+        assert_eq!(cursor, Cursor::Single((0, 2)));
+
+        let cursor = state.home(cursor);
+        assert_eq!(cursor, Cursor::Single((0, 0)));
+        assert_eq!(state.end(cursor), Cursor::Single((0, 2)));
+        assert_eq!(state.end(Cursor::Single((3, 4))), Cursor::Single((3, 0)));
+
+        assert_eq!(Cursor::Single((3, 1)).page(-10), Cursor::Single((0, 1)));
+        assert_eq!(Cursor::Single((0, 1)).page(-10), Cursor::Single((0, 1)));
+    }
+
+    #[test]
+    fn test_insert_delete() {
+        // A1:C1 hold 1, 2 and a total; A2 and B3 refer into them
+        let mut state = state_with(&[
+            ((0, 0), "1"),
+            ((0, 1), "2"),
+            ((0, 2), "=SUM(A1:B1)"),
+            ((1, 0), "=$B$1 * 2"),
+            ((2, 1), "=C1"),
+        ]);
+        state.set_column_width(1, 12);
+        state.history.commit();
+
+        state.insert_selected(Cursor::Column(1));
+        state.history.commit();
+        assert_eq!(state.get_at((0, 1)).value, "");
+        assert_eq!(state.get_at((0, 2)).value, "2");
+        assert_eq!(state.get_at((0, 3)).value, "=SUM(A1:C1)");
+        assert_eq!(state.get_at((1, 0)).value, "=$C$1 * 2");
+        assert_eq!(state.get_at((2, 2)).value, "=D1");
+        assert_eq!(state.column_width(2), 12);
+
+        state.delete_selected(Cursor::Column(2));
+        state.history.commit();
+        assert_eq!(state.get_at((0, 2)).value, "=SUM(A1:B1)");
+        assert_eq!(state.get_at((1, 0)).value, "=#REF! * 2");
+        assert_eq!(state.get_at((2, 2)).value, "");
+        assert_eq!(state.column_width(2), DEFAULT_WIDTH);
+
+        state.delete_selected(Cursor::Row(0));
+        assert_eq!(state.get_at((0, 0)).value, "=#REF! * 2");
+        assert_eq!(state.get_at((0, 2)).value, "");
+
+        // the cursor has to be on a whole row or column
+        state.delete_selected(Cursor::Single((0, 0)));
+        assert_eq!(state.get_at((0, 0)).value, "=#REF! * 2");
+
+        // undoing puts back what was deleted along with the formulas that referred to it
+        state.undo();
+        state.undo();
+        assert_eq!(state.get_at((0, 2)).value, "2");
+        assert_eq!(state.get_at((0, 3)).value, "=SUM(A1:C1)");
+        assert_eq!(state.get_at((1, 0)).value, "=$C$1 * 2");
+        assert_eq!(state.get_at((2, 2)).value, "=D1");
+        assert_eq!(state.column_width(2), 12);
+
+        state.undo();
+        assert_eq!(state.get_at((0, 1)).value, "2");
+        assert_eq!(state.get_at((0, 2)).value, "=SUM(A1:B1)");
+        assert_eq!(state.column_width(1), 12);
+
+        state.redo();
+        state.redo();
+        state.redo();
+        assert_eq!(state.get_at((0, 0)).value, "=#REF! * 2");
+        assert_eq!(state.get_at((0, 1)).value, "");
+
+        crate::compute::bake(&mut state);
+        assert_eq!(state.get_at((0, 0)).computed.display, "#REF!");
+    }
+}
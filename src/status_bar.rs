@@ -1,22 +1,24 @@
-use crate::state::{Cursor, Mode, State};
+use crate::view::{Mode, View};
 use crate::window::Window;
+use cell::compute::{format_column, format_reference, Value};
+use cell::state::{Cursor, State};
 use termion::color;
 use termion::color::Color;
 
 pub enum StatusBar {}
 
 impl StatusBar {
-    pub fn draw(window: &mut dyn Window, state: &State) {
+    pub fn draw(window: &mut dyn Window, state: &State, view: &View) {
         let (width, height) = window.size();
 
         write!(
             window,
             "{}{}",
-            color::Bg(Self::get_color(state)),
+            color::Bg(Self::get_color(view)),
             color::Fg(color::Black)
         );
 
-        let status_message = Self::get_status_message(state);
+        let status_message = Self::get_status_message(state, view);
 
         for y in 0..height {
             window.go_to(1, y + 1);
@@ -24,66 +26,189 @@ impl StatusBar {
             for x in 0..width {
                 let mut chars = status_message.chars();
 
-                if let Mode::Edit = state.mode {
-                    if x as usize == state.edit_cursor + 1 {
+                if let Mode::Edit = view.mode {
+                    if x as usize == view.edit_cursor {
                         write!(
                             window,
                             "{}{}",
                             color::Bg(color::Black),
                             color::Fg(color::White)
                         );
-                    } else if x as usize == state.edit_cursor + 2 {
+                    } else if x as usize == view.edit_cursor + 1 {
                         write!(
                             window,
                             "{}{}",
-                            color::Bg(Self::get_color(state)),
+                            color::Bg(Self::get_color(view)),
                             color::Fg(color::Black)
                         );
                     }
                 }
 
-                write!(
-                    window,
-                    "{}",
-                    chars.nth(x as usize).unwrap_or(' ').to_string()
-                )
+                write!(window, "{}", chars.nth(x as usize).unwrap_or(' '))
             }
         }
     }
 
-    pub fn get_color(State { mode, .. }: &State) -> &dyn Color {
+    pub fn get_color(View { mode, .. }: &View) -> &dyn Color {
         match mode {
             Mode::Nav => &color::LightBlue,
             Mode::Edit => &color::LightGreen,
+            Mode::Command => &color::LightYellow,
+            Mode::Quit => &color::LightRed,
         }
     }
 
-    pub fn get_status_message(state: &State) -> String {
-        match state.mode {
+    pub fn get_status_message(state: &State, view: &View) -> String {
+        if let Some(message) = &view.message {
+            return message.clone();
+        }
+
+        match view.mode {
             Mode::Nav => format!(
                 "Cursor: {}",
-                match state.cursor {
-                    Cursor::Single((r, c)) => {
-                        let cell = state.get_at((r, c));
+                match view.cursor {
+                    Cursor::Single(addr) => {
+                        let cell = state.get_at(addr);
                         format!(
-                            "{}{} ERR:{} {}",
-                            r + 1,
-                            State::col_name(c as u8 + 1),
-                            cell.computed.error,
-                            cell.computed.display
+                            "{} {}",
+                            format_reference(addr),
+                            match &cell.computed.error {
+                                Some(err) => err.to_string(),
+                                None => cell.computed.display.clone(),
+                            }
                         )
                     }
+                    Cursor::Range(..) => Self::get_range_message(state, view.cursor),
                     Cursor::Row(r) => format!("{r}:{r}", r = r + 1),
-                    Cursor::Column(c) => format!("{c}:{c}", c = State::col_name(c as u8 + 1)),
+                    Cursor::Column(c) => format!("{c}:{c}", c = format_column(c)),
                 }
             ),
-            Mode::Edit => format!(
-                "={}",
-                match state.cursor {
-                    Cursor::Single(addr) => &state.get_at(addr).value,
-                    _ => panic!("Editing with non-single select!"),
-                },
-            ),
+            Mode::Edit => match view.cursor {
+                Cursor::Single(addr) => state.get_at(addr).value.clone(),
+                _ => panic!("Editing with non-single select!"),
+            },
+            Mode::Command => format!(":{}", view.command),
+            Mode::Quit => "Save changes before quitting? (y/n)".to_string(),
         }
     }
+
+    // The selected range, with the count of its non-empty cells and the sum and average
+    // of those that hold numbers
+    fn get_range_message(state: &State, cursor: Cursor) -> String {
+        let (start, end) = cursor.bounds();
+        let mut message = format!("{}:{}", format_reference(start), format_reference(end));
+
+        let cells: Vec<_> = state
+            .cells_in((start, end))
+            .into_iter()
+            .map(|addr| state.get_at(addr))
+            .filter(|cell| !cell.value.is_empty())
+            .collect();
+        message += &format!(" Count: {}", cells.len());
+
+        let numbers: Vec<f64> = cells
+            .iter()
+            .filter_map(|cell| match cell.computed.value {
+                Some(Value::Number(num)) => Some(num),
+                _ => None,
+            })
+            .collect();
+
+        if !numbers.is_empty() {
+            let sum: f64 = numbers.iter().sum();
+            message += &format!(
+                " Sum: {} Avg: {}",
+                Value::Number(sum),
+                Value::Number(sum / numbers.len() as f64)
+            );
+        }
+
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::tests::sheet;
+    use crate::window::virtual_screen::VirtualScreen;
+    use crate::window::Frame;
+    use cell::compute::bake;
+    use cell::state::DisplayCell;
+
+    // the bar along the bottom of a small screen
+    fn render(state: &State, view: &View) -> VirtualScreen {
+        let screen = VirtualScreen::new((40, 3));
+        StatusBar::draw(&mut Frame::new(&screen, (0, 2), (40, 1)), state, view);
+        screen
+    }
+
+    #[test]
+    fn test_draw_nav() {
+        let mut state = sheet();
+        state.set_at((1, 2), DisplayCell::new("=1 / 0".to_string()));
+        bake(&mut state);
+        let mut view = View::new();
+        let screen = render(&state, &view);
+        assert_eq!(screen.text(), "\n\nCursor: B2 30");
+        assert_eq!(screen.cell_at(1, 3).bg, Some(12));
+        assert_eq!(screen.cell_at(40, 3).bg, Some(12));
+
+        view.cursor = Cursor::Single((1, 2));
+        assert_eq!(
+            render(&state, &view).text(),
+            "\n\nCursor: C2 #DIV/0! Divide by zero @ C2"
+        );
+
+        view.cursor = Cursor::Range((0, 0), (1, 1));
+        assert_eq!(
+            render(&state, &view).text(),
+            "\n\nCursor: A1:B2 Count: 3 Sum: 42 Avg: 21"
+        );
+
+        view.cursor = Cursor::Column(1);
+        assert_eq!(render(&state, &view).text(), "\n\nCursor: B:B");
+        view.cursor = Cursor::Column(27);
+        assert_eq!(render(&state, &view).text(), "\n\nCursor: AB:AB");
+        view.cursor = Cursor::Single((0, 25));
+        assert_eq!(render(&state, &view).text(), "\n\nCursor: Z1");
+    }
+
+    #[test]
+    fn test_draw_edit() {
+        let state = sheet();
+        let mut view = View::new();
+        view.mode = Mode::Edit;
+        view.edit_cursor = 3;
+        let screen = render(&state, &view);
+
+        // the character after the edit cursor is inverted
+        assert_eq!(screen.text(), "\n\n=A1 * 2.5");
+        assert_eq!(screen.cell_at(3, 3).bg, Some(10));
+        assert_eq!(screen.cell_at(4, 3).bg, Some(0));
+        assert_eq!(screen.cell_at(4, 3).fg, Some(7));
+        assert_eq!(screen.cell_at(5, 3).bg, Some(10));
+    }
+
+    #[test]
+    fn test_draw_command() {
+        let state = sheet();
+        let mut view = View::new();
+        view.mode = Mode::Command;
+        view.command = "w out.cell".to_string();
+        let screen = render(&state, &view);
+        assert_eq!(screen.text(), "\n\n:w out.cell");
+        assert_eq!(screen.cell_at(1, 3).bg, Some(11));
+
+        view.mode = Mode::Quit;
+        assert_eq!(
+            render(&state, &view).text(),
+            "\n\nSave changes before quitting? (y/n)"
+        );
+
+        // messages take the place of the usual status
+        view.mode = Mode::Nav;
+        view.message = Some("Saved out.cell".to_string());
+        assert_eq!(render(&state, &view).text(), "\n\nSaved out.cell");
+    }
 }
@@ -0,0 +1,37 @@
+use cell::state::{Address, Clipboard, Cursor};
+
+// How the TUI is showing and editing a sheet, which isn't part of the sheet itself
+pub struct View {
+    pub mode: Mode,
+    pub cursor: Cursor,
+    pub scroll: Address,
+    // where typing goes in the cell being edited
+    pub edit_cursor: usize,
+    pub clipboard: Option<Clipboard>,
+    // the text typed after `:` in Command mode
+    pub command: String,
+    // shown in the status bar until the next key
+    pub message: Option<String>,
+}
+
+impl View {
+    pub fn new() -> Self {
+        View {
+            mode: Mode::Nav,
+            cursor: Cursor::Single((1, 1)),
+            scroll: (0, 0),
+            edit_cursor: 0,
+            clipboard: None,
+            command: String::new(),
+            message: None,
+        }
+    }
+}
+
+pub enum Mode {
+    Nav,
+    Edit,
+    Command,
+    // asking whether to save before quitting
+    Quit,
+}
@@ -1,7 +1,12 @@
 use std::cell::RefCell;
 use std::fmt::Arguments;
-use std::io::{stdout, Write};
+use std::io::{stdin, stdout, Write};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
 use termion::cursor::HideCursor;
+use termion::event::Key;
+use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use termion::screen::IntoAlternateScreen;
 
@@ -59,6 +64,52 @@ impl Window for Screen {
     }
 }
 
+// How often to check whether the terminal has been resized
+const RESIZE_POLL: Duration = Duration::from_millis(100);
+
+// Something the main loop reacts to
+pub enum Event {
+    Key(Key),
+    Resize,
+    // there are no more keys to read, as stdin was closed
+    Closed,
+}
+
+// Keys as they're pressed, along with changes to the size of the terminal. Each is
+// watched on a thread of its own so a resize is redrawn without waiting for a key.
+// Both threads stop once the receiver is dropped and there's nothing to send to.
+pub fn events() -> Receiver<Event> {
+    let (sender, receiver) = channel();
+
+    let keys = sender.clone();
+    thread::spawn(move || {
+        for key in stdin().keys() {
+            let Ok(key) = key else { break };
+            if keys.send(Event::Key(key)).is_err() {
+                return;
+            }
+        }
+        let _ = keys.send(Event::Closed);
+    });
+
+    thread::spawn(move || {
+        let mut size = termion::terminal_size().ok();
+        loop {
+            thread::sleep(RESIZE_POLL);
+
+            let new_size = termion::terminal_size().ok();
+            if new_size != size {
+                size = new_size;
+                if sender.send(Event::Resize).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
 // A subsection of the screen
 pub struct Frame<'a> {
     parent: &'a dyn Window,
@@ -67,7 +118,7 @@ pub struct Frame<'a> {
 }
 
 impl Frame<'_> {
-    pub fn new(parent: &dyn Window, offset: (u16, u16), size: (u16, u16)) -> Frame {
+    pub fn new(parent: &dyn Window, offset: (u16, u16), size: (u16, u16)) -> Frame<'_> {
         Frame {
             parent,
             offset,
@@ -103,3 +154,150 @@ impl Window for Frame<'_> {
         self.parent.flush()
     }
 }
+
+#[cfg(test)]
+pub mod virtual_screen {
+    use super::Window;
+    use std::cell::{Cell, RefCell};
+    use std::fmt::Arguments;
+
+    // A screen kept in memory, which reads the cursor movements and colours written to it
+    // the way a terminal would, so what gets drawn can be checked in tests
+    pub struct VirtualScreen {
+        size: (u16, u16),
+        grid: RefCell<Vec<Vec<VirtualCell>>>,
+        // 0-based position the next character is written to
+        cursor: Cell<(u16, u16)>,
+        // the colours the next character is written with
+        colors: Cell<(Option<u8>, Option<u8>)>,
+    }
+
+    // A character on a virtual screen, along with its foreground and background colours
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub struct VirtualCell {
+        pub char: char,
+        pub fg: Option<u8>,
+        pub bg: Option<u8>,
+    }
+
+    impl VirtualScreen {
+        pub fn new(size: (u16, u16)) -> Self {
+            let blank = VirtualCell {
+                char: ' ',
+                fg: None,
+                bg: None,
+            };
+
+            VirtualScreen {
+                size,
+                grid: RefCell::new(vec![vec![blank; size.0 as usize]; size.1 as usize]),
+                cursor: Cell::new((0, 0)),
+                colors: Cell::new((None, None)),
+            }
+        }
+
+        // The characters on screen, one line per row with trailing spaces dropped
+        pub fn text(&self) -> String {
+            let lines: Vec<String> = self
+                .grid
+                .borrow()
+                .iter()
+                .map(|row| {
+                    let line: String = row.iter().map(|cell| cell.char).collect();
+                    line.trim_end().to_string()
+                })
+                .collect();
+
+            lines.join("\n")
+        }
+
+        // The cell at a 1-based position, as given to go_to
+        pub fn cell_at(&self, x: u16, y: u16) -> VirtualCell {
+            self.grid.borrow()[y as usize - 1][x as usize - 1]
+        }
+
+        fn put(&self, char: char) {
+            let (x, y) = self.cursor.get();
+            let (fg, bg) = self.colors.get();
+
+            if x < self.size.0 && y < self.size.1 {
+                self.grid.borrow_mut()[y as usize][x as usize] = VirtualCell { char, fg, bg };
+            }
+            self.cursor.set((x + 1, y));
+        }
+
+        // Act on a CSI escape sequence, given its parameters and final character
+        fn escape(&self, params: &str, action: char) {
+            let numbers: Vec<u16> = params.split(';').filter_map(|n| n.parse().ok()).collect();
+
+            match (action, numbers.as_slice()) {
+                ('H', [y, x]) => self.cursor.set((x.saturating_sub(1), y.saturating_sub(1))),
+                ('m', [38, 5, color]) => self.colors.set((Some(*color as u8), self.colors.get().1)),
+                ('m', [48, 5, color]) => self.colors.set((self.colors.get().0, Some(*color as u8))),
+                ('m', [39]) => self.colors.set((None, self.colors.get().1)),
+                ('m', [49]) => self.colors.set((self.colors.get().0, None)),
+                ('m', [] | [0]) => self.colors.set((None, None)),
+                _ => {}
+            }
+        }
+    }
+
+    impl Window for VirtualScreen {
+        fn size(&self) -> (u16, u16) {
+            self.size
+        }
+
+        fn go_to(&self, x: u16, y: u16) {
+            write!(self, "{}", termion::cursor::Goto(x, y));
+        }
+
+        fn write_fmt(&self, fmt: Arguments<'_>) {
+            let text = fmt.to_string();
+            let mut chars = text.chars();
+
+            while let Some(char) = chars.next() {
+                if char != '\x1b' {
+                    self.put(char);
+                    continue;
+                }
+
+                // ESC [ params action
+                chars.next();
+                let mut params = String::new();
+                for char in chars.by_ref() {
+                    if ('@'..='~').contains(&char) {
+                        self.escape(&params, char);
+                        break;
+                    }
+                    params.push(char);
+                }
+            }
+        }
+
+        fn flush(&self) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::virtual_screen::VirtualScreen;
+    use super::*;
+    use termion::{color, style};
+
+    #[test]
+    fn test_virtual_screen() {
+        let screen = VirtualScreen::new((6, 3));
+        let frame = Frame::new(&screen, (1, 1), (4, 2));
+
+        frame.go_to(1, 1);
+        write!(frame, "ab{}c{}d", color::Bg(color::LightCyan), style::Reset);
+        frame.go_to(4, 2);
+        write!(frame, "{}xyz", color::Fg(color::Red));
+
+        assert_eq!(screen.text(), "\n abcd\n    xy");
+        assert_eq!(screen.cell_at(2, 2).bg, None);
+        assert_eq!(screen.cell_at(4, 2).bg, Some(14));
+        assert_eq!(screen.cell_at(5, 2).bg, None);
+        assert_eq!(screen.cell_at(5, 3).fg, Some(1));
+    }
+}